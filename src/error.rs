@@ -0,0 +1,34 @@
+use std::fmt;
+
+/// Errors that can occur when generating the hashes for an index or a query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SearchHelperError {
+    /// The input was longer than the maximum length this approach supports.
+    InputTooLong { actual_len: usize, max_len: usize },
+    /// The options passed in can't be used together, or are out of range.
+    InvalidConfiguration(String),
+    /// An empty salt or key was passed in, which would leave the hashes unkeyed.
+    EmptySalt,
+}
+
+impl fmt::Display for SearchHelperError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SearchHelperError::InputTooLong {
+                actual_len,
+                max_len,
+            } => write!(
+                f,
+                "The input string is too long. This function only supports strings that are no longer than {} chars, but got {}.",
+                max_len, actual_len
+            ),
+            SearchHelperError::InvalidConfiguration(msg) => {
+                write!(f, "Invalid configuration: {}", msg)
+            }
+            SearchHelperError::EmptySalt => write!(f, "The salt or key must not be empty."),
+        }
+    }
+}
+
+impl std::error::Error for SearchHelperError {}
@@ -0,0 +1,65 @@
+use std::collections::HashSet;
+
+const FILTERED_CHARS: [char; 31] = [
+    '!', '@', '#', '$', '%', '^', '&', '*', '(', ')', '{', '}', '_', '<', '>', ':', ';', ',', '.',
+    '"', '\'', '`', '|', '+', '=', '/', '~', '[', ']', '\\', '-',
+];
+
+/// Which characters are kept when a string is normalized, before it is transliterated and split into words.
+/// Whatever is removed no longer separates words, so "jean-luc" is a single word under `CharFilter::Standard`,
+/// but two words under a filter that keeps '-'.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CharFilter {
+    /// Remove 31 ASCII punctuation characters, such as '@', '.' and '-'.
+    Standard,
+    /// Remove only these characters.
+    Deny(HashSet<char>),
+    /// Keep only these characters, along with whitespace so the words can still be told apart.
+    Allow(HashSet<char>),
+    /// Keep only letters, numbers and whitespace, removing all other punctuation and symbols.
+    Alphanumeric,
+}
+
+impl Default for CharFilter {
+    fn default() -> CharFilter {
+        CharFilter::Standard
+    }
+}
+
+impl CharFilter {
+    ///True if we should keep the character in the string.
+    pub(crate) fn keeps(&self, c: char) -> bool {
+        match self {
+            CharFilter::Standard => !FILTERED_CHARS.contains(&c),
+            CharFilter::Deny(chars) => !chars.contains(&c),
+            CharFilter::Allow(chars) => c.is_whitespace() || chars.contains(&c),
+            CharFilter::Alphanumeric => c.is_alphanumeric() || c.is_whitespace(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keep(filter: &CharFilter, s: &str) -> String {
+        s.chars().filter(|&c| filter.keeps(c)).collect()
+    }
+
+    #[test]
+    fn char_filter_standard() {
+        assert_eq!(keep(&CharFilter::default(), "a.b@c-d é!"), "abcd é");
+    }
+
+    #[test]
+    fn char_filter_deny_and_allow() {
+        let chars: HashSet<char> = ['@', '.'].iter().copied().collect();
+        assert_eq!(keep(&CharFilter::Deny(chars.clone()), "a.b@c-d"), "abc-d");
+        assert_eq!(keep(&CharFilter::Allow(chars), "a.b @c"), ". @");
+    }
+
+    #[test]
+    fn char_filter_alphanumeric() {
+        assert_eq!(keep(&CharFilter::Alphanumeric, "a.b€ 2¿志"), "ab 2志");
+    }
+}
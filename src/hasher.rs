@@ -0,0 +1,259 @@
+use crate::SearchHelperError;
+use hmac::{Hmac, Mac};
+use sha2::digest::Update;
+use sha2::{Digest, Sha256, Sha512_256};
+
+///Tag hashed ahead of everything else in the V2 scheme so its hashes can never line up with V1 hashes.
+const V2_DOMAIN_TAG: &[u8] = b"ironcore-search-helpers/v2";
+
+/// A hash function used to turn each token into an index entry.
+/// Implementations are expected to already be keyed by the partition_id and salt, so the same token
+/// in two different partitions (or with two different salts) gives unrelated output.
+pub trait BlindIndexHasher {
+    /// Hash a single token. The result will be truncated before it goes into the index.
+    fn hash_token(&self, token: &[u8]) -> [u8; 32];
+}
+
+/// The scheme used to combine the partition_id, salt and tri-gram before they are hashed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HashVersion {
+    /// The partition_id, salt and tri-gram are concatenated with nothing between them. This is ambiguous (partition_id "ab"
+    /// with salt "c" hashes the same as partition_id "a" with salt "bc"), but is kept so existing indexes continue to match.
+    V1,
+    /// A version tag is hashed first and the partition_id, salt and tri-gram are each prefixed with their length,
+    /// so distinct inputs can never be framed the same way. A missing partition_id is also distinct from an empty one.
+    V2,
+}
+
+/// SHA-256 over the partition_id, salt and token, combined using a `HashVersion`.
+/// An empty salt is rejected for `HashVersion::V2`, but still accepted for `HashVersion::V1` so existing indexes keep working.
+#[derive(Clone)]
+pub struct Sha256Hasher {
+    partial_sha256: Sha256,
+    version: HashVersion,
+}
+
+impl Sha256Hasher {
+    pub fn new(
+        partition_id: Option<&str>,
+        salt: &[u8],
+        version: HashVersion,
+    ) -> Result<Sha256Hasher, SearchHelperError> {
+        if version == HashVersion::V2 && salt.is_empty() {
+            return Err(SearchHelperError::EmptySalt);
+        }
+        //Compute a partial sha256 with the partition_id and the salt - We can reuse this for each word
+        let partial_sha256 = match version {
+            HashVersion::V1 => partition_id
+                .map(|k| k.as_bytes())
+                .iter()
+                .chain([salt].iter())
+                .fold(Sha256::new(), |hasher, k| hasher.chain(k)),
+            HashVersion::V2 => {
+                chain_framed_partition(Sha256::new().chain(V2_DOMAIN_TAG), partition_id)
+                    .chain_framed(salt)
+            }
+        };
+        Ok(Sha256Hasher {
+            partial_sha256,
+            version,
+        })
+    }
+}
+
+impl BlindIndexHasher for Sha256Hasher {
+    fn hash_token(&self, token: &[u8]) -> [u8; 32] {
+        let sha256_hash = match self.version {
+            HashVersion::V1 => self.partial_sha256.clone().chain(token),
+            HashVersion::V2 => self.partial_sha256.clone().chain_framed(token),
+        };
+        sha256_hash.finalize().into()
+    }
+}
+
+/// HMAC-SHA256 keyed by the salt over the partition_id and token, with both prefixed by their length.
+/// This lets the index be reasoned about as a keyed PRF rather than a salted hash. The key must not be empty.
+#[derive(Clone)]
+pub struct HmacSha256Hasher {
+    partial_hmac: Hmac<Sha256>,
+}
+
+impl HmacSha256Hasher {
+    pub fn new(
+        partition_id: Option<&str>,
+        key: &[u8],
+    ) -> Result<HmacSha256Hasher, SearchHelperError> {
+        if key.is_empty() {
+            return Err(SearchHelperError::EmptySalt);
+        }
+        //HMAC accepts keys of any length, so this can't fail.
+        let hmac =
+            <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC can take a key of any size");
+        //Keying the HMAC computes the inner and outer states, which we can reuse along with the partition_id for each word
+        Ok(HmacSha256Hasher {
+            partial_hmac: chain_framed_partition(hmac, partition_id),
+        })
+    }
+}
+
+impl BlindIndexHasher for HmacSha256Hasher {
+    fn hash_token(&self, token: &[u8]) -> [u8; 32] {
+        let hmac = self.partial_hmac.clone().chain_framed(token);
+        hmac.finalize().into_bytes().into()
+    }
+}
+
+/// BLAKE3 in keyed mode over the partition_id and token, with both prefixed by their length.
+/// BLAKE3 requires a key of exactly 32 bytes. Only available with the `blake3` feature.
+#[cfg(feature = "blake3")]
+#[derive(Clone)]
+pub struct Blake3Hasher {
+    partial_blake3: Blake3State,
+}
+
+#[cfg(feature = "blake3")]
+impl Blake3Hasher {
+    pub fn new(partition_id: Option<&str>, key: &[u8; 32]) -> Blake3Hasher {
+        Blake3Hasher {
+            partial_blake3: chain_framed_partition(
+                Blake3State(blake3::Hasher::new_keyed(key)),
+                partition_id,
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "blake3")]
+impl BlindIndexHasher for Blake3Hasher {
+    fn hash_token(&self, token: &[u8]) -> [u8; 32] {
+        let Blake3State(blake3_hash) = self.partial_blake3.clone().chain_framed(token);
+        blake3_hash.finalize().into()
+    }
+}
+
+/// SHA-512/256 over the partition_id, salt and token, framed the same way as `HashVersion::V2`. The salt must not be empty.
+/// This is usually faster than SHA-256 on 64 bit machines and is FIPS approved.
+#[derive(Clone)]
+pub struct Sha512_256Hasher {
+    partial_sha512_256: Sha512_256,
+}
+
+impl Sha512_256Hasher {
+    pub fn new(
+        partition_id: Option<&str>,
+        salt: &[u8],
+    ) -> Result<Sha512_256Hasher, SearchHelperError> {
+        if salt.is_empty() {
+            return Err(SearchHelperError::EmptySalt);
+        }
+        Ok(Sha512_256Hasher {
+            partial_sha512_256: chain_framed_partition(
+                Sha512_256::new().chain(V2_DOMAIN_TAG),
+                partition_id,
+            )
+            .chain_framed(salt),
+        })
+    }
+}
+
+impl BlindIndexHasher for Sha512_256Hasher {
+    fn hash_token(&self, token: &[u8]) -> [u8; 32] {
+        let sha512_256_hash = self.partial_sha512_256.clone().chain_framed(token);
+        sha512_256_hash.finalize().into()
+    }
+}
+
+///blake3 doesn't implement the digest traits without an unstable feature, so we adapt it ourselves.
+#[cfg(feature = "blake3")]
+#[derive(Clone)]
+struct Blake3State(blake3::Hasher);
+
+#[cfg(feature = "blake3")]
+impl Update for Blake3State {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+}
+
+///Extension for feeding unambiguously framed values into a hash or MAC.
+trait ChainFramed: Update + Sized {
+    ///Feed bytes into the hasher prefixed by their length as a big endian u64.
+    fn chain_framed(self, bytes: &[u8]) -> Self {
+        self.chain((bytes.len() as u64).to_be_bytes()).chain(bytes)
+    }
+}
+
+impl<D: Update> ChainFramed for D {}
+
+///Feed the partition_id into the hasher, marking whether it was present so that None and Some("") differ.
+fn chain_framed_partition<D: Update>(hasher: D, partition_id: Option<&str>) -> D {
+    match partition_id {
+        None => hasher.chain([0u8]),
+        Some(p) => hasher.chain([1u8]).chain_framed(p.as_bytes()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "blake3")]
+    #[test]
+    fn blake3_hasher_compute_known_value() {
+        let key = [7u8; 32];
+        let result = Blake3Hasher::new(Some("foo"), &key).hash_token(b"123");
+        let expected_result = {
+            let mut hasher = blake3::Hasher::new_keyed(&key);
+            hasher.update(&[1u8]);
+            hasher.update(&3u64.to_be_bytes());
+            hasher.update(b"foo");
+            hasher.update(&3u64.to_be_bytes());
+            hasher.update(b"123");
+            <[u8; 32]>::from(hasher.finalize())
+        };
+        assert_eq!(result, expected_result);
+    }
+
+    #[test]
+    fn sha512_256_hasher_compute_known_value() {
+        let result = Sha512_256Hasher::new(None, b"salt")
+            .unwrap()
+            .hash_token(b"123");
+        let expected_result = {
+            let mut hasher = Sha512_256::new();
+            sha2::Digest::update(&mut hasher, V2_DOMAIN_TAG);
+            sha2::Digest::update(&mut hasher, [0u8]);
+            sha2::Digest::update(&mut hasher, 4u64.to_be_bytes());
+            sha2::Digest::update(&mut hasher, b"salt");
+            sha2::Digest::update(&mut hasher, 3u64.to_be_bytes());
+            sha2::Digest::update(&mut hasher, b"123");
+            <[u8; 32]>::from(hasher.finalize())
+        };
+        assert_eq!(result, expected_result);
+    }
+
+    #[test]
+    fn hashers_are_reusable_across_tokens() {
+        let hasher = HmacSha256Hasher::new(Some("foo"), b"key").unwrap();
+        let first = hasher.hash_token(b"abc");
+        assert_ne!(first, hasher.hash_token(b"bcd"));
+        assert_eq!(first, hasher.hash_token(b"abc"));
+    }
+
+    #[test]
+    fn hashers_reject_empty_salt() {
+        assert_eq!(
+            HmacSha256Hasher::new(Some("foo"), b"").err(),
+            Some(SearchHelperError::EmptySalt)
+        );
+        assert_eq!(
+            Sha512_256Hasher::new(Some("foo"), b"").err(),
+            Some(SearchHelperError::EmptySalt)
+        );
+        assert_eq!(
+            Sha256Hasher::new(Some("foo"), b"", HashVersion::V2).err(),
+            Some(SearchHelperError::EmptySalt)
+        );
+        assert!(Sha256Hasher::new(Some("foo"), b"", HashVersion::V1).is_ok());
+    }
+}
@@ -1,28 +1,39 @@
-use itertools::*;
+mod error;
+mod filter;
+mod hasher;
+mod normalize;
+mod phonetic;
+mod score;
+mod stem;
+mod tokenize;
+mod verify;
+
+pub use error::SearchHelperError;
+pub use filter::CharFilter;
+#[cfg(feature = "blake3")]
+pub use hasher::Blake3Hasher;
+pub use hasher::{BlindIndexHasher, HashVersion, HmacSha256Hasher, Sha256Hasher, Sha512_256Hasher};
 use lazy_static::*;
+pub use normalize::{NormalizationProfile, TransliterationProfile};
 use rand::distributions::*;
 use rand::{CryptoRng, Rng};
-use sha2::digest::Update;
-use sha2::{Digest, Sha256};
+pub use score::{score_candidate, MatchScore, ScoreThreshold};
 use std::collections::HashSet;
+use std::hash::Hash;
 use std::ops::DerefMut;
 use std::sync::{Mutex, MutexGuard};
-use unicode_segmentation::UnicodeSegmentation;
-use unidecode::unidecode_char;
+pub use tokenize::{english_stop_words, NgramMode};
+use tokenize::{
+    index_words, make_exact_token, make_fuzzy_tokens_by_word, make_phonetic_query_tokens,
+    make_query_tokens, make_tokens, make_word_query_tokens, transliterate_with_config, Token,
+    TokenDomain,
+};
+pub use verify::{verify_candidate, MatchMode};
 use Result::{Err, Ok};
 
-const FILTERED_CHARS: [char; 31] = [
-    '!', '@', '#', '$', '%', '^', '&', '*', '(', ')', '{', '}', '_', '<', '>', ':', ';', ',', '.',
-    '"', '\'', '`', '|', '+', '=', '/', '~', '[', ']', '\\', '-',
-];
-
-///True if we should keep the character in the string.
-fn should_keep_char(c: &char) -> bool {
-    !FILTERED_CHARS.contains(c)
-}
 lazy_static! {
-    ///Special chars that should be filtered out.
-    static ref ALL_U32: Uniform<u32> = Uniform::new_inclusive(0u32, u32::max_value());
+    ///Used to draw the random entries for padding.
+    static ref ALL_U32: Uniform<u32> = Uniform::new_inclusive(0u32, u32::MAX);
     //We use this so we don't have to generate the floating numbers and do comparisons on them. It allows us to do 1/2 percent level scaling.
     static ref ONE_TO_TWO_HUNDRED: Uniform<u8> = Uniform::new_inclusive(1, 200);
 }
@@ -30,6 +41,188 @@ lazy_static! {
 ///Something over 200 chars isn't really suitable for this approach, so we won't accept it.
 const MAX_STRING_LEN: usize = 200;
 
+///A search with fewer tokens than this is likely to match a large part of the index.
+const MIN_QUERY_TOKENS: usize = 2;
+
+///Tri-grams by default.
+const DEFAULT_NGRAM_SIZE: usize = 3;
+
+///Long text is broken into at most this many chunks by default, which allows 10,000 chars.
+const MAX_CHUNKS: usize = 50;
+
+/// How many bits of the digest are kept for each entry in the index. Wider entries make the index larger,
+/// but give far fewer spurious candidates once a partition holds many tri-grams.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HashWidth {
+    /// The same width used by `generate_hashes_for_string`.
+    Bits32,
+    Bits48,
+    Bits64,
+}
+
+impl HashWidth {
+    ///The largest value an entry of this width can take.
+    fn max_value(self) -> u64 {
+        match self {
+            HashWidth::Bits32 => u32::MAX as u64,
+            HashWidth::Bits48 => (1 << 48) - 1,
+            HashWidth::Bits64 => u64::MAX,
+        }
+    }
+
+    ///Interpret the most significant bytes of the digest as a big endian number of this width.
+    fn truncate(self, digest: &[u8; 32]) -> u64 {
+        match self {
+            HashWidth::Bits32 => as_u32_be(digest) as u64,
+            HashWidth::Bits48 => as_u64_be(digest) >> 16,
+            HashWidth::Bits64 => as_u64_be(digest),
+        }
+    }
+}
+
+/// Options that control how an index is built.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexConfig {
+    /// The most characters (not bytes) an input may have. Padding never takes an index past this many entries for each
+    /// token an input character can make with the other options (e.g. two more for `fuzzy_tokens`), though it always
+    /// adds at least one entry.
+    pub max_len: usize,
+    /// How many bits of the digest are kept for each entry in the index.
+    pub hash_width: HashWidth,
+    /// How many characters are in each n-gram. Short codes may do better with bigrams and long identifiers with 4 or 5-grams.
+    /// Words shorter than this are padded with '-'. Must be at least 2.
+    pub ngram_size: usize,
+    /// Which n-grams of each word go into the index.
+    pub ngram_mode: NgramMode,
+    /// Also put each whole word into the index, under its own domain tag, so `generate_word_query_hashes` can search for
+    /// documents that contain a word. This is far more selective than intersecting n-grams.
+    pub word_tokens: bool,
+    /// Also put the Soundex code of each word into the index, under its own domain tag, so
+    /// `generate_phonetic_query_hashes` can find names that sound alike even when they are spelled differently
+    /// (e.g. "Jon" and "John", or "Smyth" and "Smith"). Words with no letters don't get a code.
+    pub phonetic_tokens: bool,
+    /// Also put each word, and each way of taking one character out of it, into the index under their own domain tag, so
+    /// `generate_fuzzy_query_hashes` can find words with a one character typo. Words shorter than 3 characters are
+    /// only put in whole. This adds about as many entries as there are characters in the input.
+    pub fuzzy_tokens: bool,
+    /// Reduce each English word to its stem (using the Porter algorithm) before making n-grams and word tokens, so that
+    /// a search for "running" finds "runs". This suits free-text fields, but not names or identifiers.
+    /// Exact and phonetic tokens are made from the words as written. This can't be used with `NgramMode::Prefix`,
+    /// since a partly typed word has no stem.
+    pub stem_words: bool,
+    /// Words that are left out of the n-grams and the word and phonetic tokens, on both the index and query side.
+    /// Very common words match nearly every document and give away how often they're used, so they're best left out.
+    /// These are compared against words after they have been normalized, so they should be lowercase and unaccented.
+    /// Empty by default; `english_stop_words()` gives a built-in list for English.
+    pub stop_words: HashSet<String>,
+    /// Which characters are kept before the input is transliterated and split into words.
+    pub char_filter: CharFilter,
+    /// How the input is normalized before anything else is done to it.
+    pub normalization: NormalizationProfile,
+    /// Keep Han, Hiragana, Katakana and Hangul characters as they are, rather than romanizing them, and index each run of
+    /// them by its overlapping bigrams. Romanizing makes each character its own word and gives characters that sound
+    /// the same the same n-grams, so this is much more precise for CJK text.
+    pub cjk_bigrams: bool,
+    /// Extra transliteration rules for a locale, such as German "ü" to "ue", or no transliteration at all.
+    pub transliteration: TransliterationProfile,
+    /// The most chunks of `max_len` characters that `generate_hashes_for_long_text` will accept.
+    pub max_chunks: usize,
+    /// Queries that produce fewer distinct tokens than this are flagged as not selective by `generate_query_hashes`.
+    /// In `NgramMode::Prefix` each query word is a single token, so there a query is instead flagged unless one of its
+    /// words has at least `ngram_size` characters.
+    pub min_query_tokens: usize,
+}
+
+impl Default for IndexConfig {
+    /// The options `generate_hashes_for_string` and `transliterate_string` have always used: tri-grams of every word,
+    /// 32 bit entries, the standard char filter and transliteration, no normalization and no extra tokens.
+    fn default() -> IndexConfig {
+        IndexConfig {
+            max_len: MAX_STRING_LEN,
+            hash_width: HashWidth::Bits32,
+            ngram_size: DEFAULT_NGRAM_SIZE,
+            ngram_mode: NgramMode::All,
+            word_tokens: false,
+            phonetic_tokens: false,
+            fuzzy_tokens: false,
+            stem_words: false,
+            stop_words: HashSet::new(),
+            char_filter: CharFilter::Standard,
+            normalization: NormalizationProfile::V1,
+            cjk_bigrams: false,
+            transliteration: TransliterationProfile::Standard,
+            max_chunks: MAX_CHUNKS,
+            min_query_tokens: MIN_QUERY_TOKENS,
+        }
+    }
+}
+
+impl IndexConfig {
+    ///Make sure the options can be used to build an index.
+    fn validate(&self) -> Result<(), SearchHelperError> {
+        if self.max_len == 0 {
+            Err(SearchHelperError::InvalidConfiguration(
+                "max_len must be greater than 0".to_string(),
+            ))
+        } else if self.ngram_size < 2 {
+            Err(SearchHelperError::InvalidConfiguration(
+                "ngram_size must be at least 2".to_string(),
+            ))
+        } else if self.stem_words && self.ngram_mode == NgramMode::Prefix {
+            Err(SearchHelperError::InvalidConfiguration(
+                "stem_words can't be used with NgramMode::Prefix".to_string(),
+            ))
+        } else if self.max_chunks == 0 {
+            Err(SearchHelperError::InvalidConfiguration(
+                "max_chunks must be greater than 0".to_string(),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    ///The most tokens a single input character can add to an index, for input that transliterates one character to one.
+    fn max_tokens_per_char(&self) -> usize {
+        //A word of n characters has at most n n-grams or prefixes, plus one more n-gram when it's anchored.
+        let mut per_char = match self.ngram_mode {
+            NgramMode::All | NgramMode::Prefix => 1,
+            NgramMode::Anchored => 2,
+        };
+        if self.word_tokens {
+            per_char += 1;
+        }
+        if self.phonetic_tokens {
+            per_char += 1;
+        }
+        //The word itself plus one token for each character taken out of it.
+        if self.fuzzy_tokens {
+            per_char += 2;
+        }
+        if self.transliteration.also_indexes_standard() {
+            per_char *= 2;
+        }
+        per_char
+    }
+
+    ///The most entries padding will take an index of at most max_len characters to.
+    fn max_padded_len(&self, max_len: usize) -> usize {
+        max_len.saturating_mul(self.max_tokens_per_char())
+    }
+
+    ///Error if s has more characters than this config allows.
+    fn check_len(&self, s: &str) -> Result<(), SearchHelperError> {
+        let actual_len = s.chars().count();
+        if actual_len > self.max_len {
+            Err(SearchHelperError::InputTooLong {
+                actual_len,
+                max_len: self.max_len,
+            })
+        } else {
+            Ok(())
+        }
+    }
+}
+
 /// Make an index, for the string s considering all tri-grams.
 /// The string will be latinised, lowercased and stripped of special chars before being broken into tri-grams.
 /// The values will be prefixed with partition_id and salt before being hashed.
@@ -40,113 +233,436 @@ pub fn generate_hashes_for_string_with_padding<R: Rng + CryptoRng>(
     partition_id: Option<&str>,
     salt: &[u8],
     rng: &Mutex<R>,
-) -> Result<HashSet<u32>, String> {
-    let mut hashes = generate_hashes_for_string(s, partition_id, salt)?;
+) -> Result<HashSet<u32>, SearchHelperError> {
+    generate_hashes_for_string_with_padding_versioned(s, partition_id, salt, HashVersion::V1, rng)
+}
 
-    let prob = take_lock(&rng).deref_mut().sample(*ONE_TO_TWO_HUNDRED);
-    let to_add: u8 = {
-        //Just take the lock once because we need it in all cases and it makes the code look better.
-        let r = &mut *take_lock(&rng);
-        if prob <= 1 {
-            r.gen_range(1..200)
-        } else if prob <= 5 {
-            r.gen_range(1..30)
-        } else if prob <= 50 {
-            r.gen_range(1..10)
-        } else {
-            r.gen_range(1..5)
-        }
-    };
-    //This will never be negative because generate_hashes_for_string would error if hashes was going to be larger than and will never be larger than MAX_STRING_LEN.
-    //This also ensures we're able to pad by at least 2 since the maximum trigram length is always 2 less than the max string length.
-    let pad_len = std::cmp::min(MAX_STRING_LEN - hashes.len(), to_add as usize);
-    hashes.extend(
-        take_lock(&rng)
-            .deref_mut()
-            .sample_iter(*ALL_U32)
-            .take(pad_len),
-    );
-    Ok(hashes)
+/// Same as `generate_hashes_for_string_with_padding`, but the partition_id, salt and tri-grams are combined
+/// using the scheme described by `version`.
+pub fn generate_hashes_for_string_with_padding_versioned<R: Rng + CryptoRng>(
+    s: &str,
+    partition_id: Option<&str>,
+    salt: &[u8],
+    version: HashVersion,
+    rng: &Mutex<R>,
+) -> Result<HashSet<u32>, SearchHelperError> {
+    let hashes = generate_hashes_for_string_versioned(s, partition_id, salt, version)?;
+    Ok(pad_hashes(hashes, *ALL_U32, MAX_STRING_LEN, rng))
 }
 
 /// Make an index, for the string s considering all tri-grams.
 /// The string will be latinised, lowercased and stripped of special chars before being broken into tri-grams.
 /// The values will be prefixed with partition_id and salt before being hashed.
 /// Each entry in the HasheSet will be truncated to 32 bits and will be encoded as a big endian number.
-/// If the string is longer than 200 characters, this will return an error.
+/// If the string has more than 200 characters, this will return an error.
 pub fn generate_hashes_for_string(
     s: &str,
     partition_id: Option<&str>,
     salt: &[u8],
-) -> Result<HashSet<u32>, String> {
-    if s.len() > MAX_STRING_LEN {
-        Err(format!("The input string is too long. This function only supports strings that are no longer than {} chars.", MAX_STRING_LEN))
-    } else {
-        //Compute a partial sha256 with the partition_id and the salt - We can reuse this for each word
-        let partial_sha256 = partition_id
-            .map(|k| k.as_bytes())
-            .iter()
-            .chain([salt].iter())
-            .fold(Sha256::new(), |hasher, k| hasher.chain(k));
+) -> Result<HashSet<u32>, SearchHelperError> {
+    generate_hashes_for_string_versioned(s, partition_id, salt, HashVersion::V1)
+}
 
-        let short_hash = |word: &[u8]| -> u32 {
-            let sha256_hash = partial_sha256.clone().chain(word);
-            as_u32_be(&sha256_hash.finalize().into())
-        };
+/// Same as `generate_hashes_for_string`, but the partition_id, salt and tri-grams are combined
+/// using the scheme described by `version`. New indexes should use `HashVersion::V2`.
+pub fn generate_hashes_for_string_versioned(
+    s: &str,
+    partition_id: Option<&str>,
+    salt: &[u8],
+    version: HashVersion,
+) -> Result<HashSet<u32>, SearchHelperError> {
+    generate_hashes(
+        s,
+        &Sha256Hasher::new(partition_id, salt, version)?,
+        &IndexConfig::default(),
+    )
+    .map(narrow_to_u32)
+}
+
+/// Make an index, for the string s considering all tri-grams, using HMAC-SHA256 keyed by `key`.
+/// The string will be latinised, lowercased and stripped of special chars before being broken into tri-grams.
+/// Each value is the HMAC of the partition_id followed by the tri-gram, with both prefixed by their length so they can't run together.
+/// Each entry in the HashSet will be truncated to 32 bits and will be encoded as a big endian number.
+/// If the string is longer than 200 characters, this will return an error.
+pub fn generate_hmac_hashes_for_string(
+    s: &str,
+    partition_id: Option<&str>,
+    key: &[u8],
+) -> Result<HashSet<u32>, SearchHelperError> {
+    generate_hashes(
+        s,
+        &HmacSha256Hasher::new(partition_id, key)?,
+        &IndexConfig::default(),
+    )
+    .map(narrow_to_u32)
+}
 
-        let result: HashSet<_> = make_tri_grams(s)
+/// Same as `generate_hmac_hashes_for_string`, but this function will also add some random entries to the HashSet
+/// to not expose how many tri-grams were actually found.
+pub fn generate_hmac_hashes_for_string_with_padding<R: Rng + CryptoRng>(
+    s: &str,
+    partition_id: Option<&str>,
+    key: &[u8],
+    rng: &Mutex<R>,
+) -> Result<HashSet<u32>, SearchHelperError> {
+    let hashes = generate_hmac_hashes_for_string(s, partition_id, key)?;
+    Ok(pad_hashes(hashes, *ALL_U32, MAX_STRING_LEN, rng))
+}
+
+/// Make an index, for the string s considering all n-grams of `config.ngram_size` characters, hashing each of them with `hasher`.
+/// The string will be latinised, lowercased and stripped of special chars before being broken into n-grams.
+/// Each entry in the HashSet will be truncated to `config.hash_width` bits and will be encoded as a big endian number.
+/// If the string has more than `config.max_len` characters, this will return an error.
+pub fn generate_hashes<H: BlindIndexHasher + ?Sized>(
+    s: &str,
+    hasher: &H,
+    config: &IndexConfig,
+) -> Result<HashSet<u64>, SearchHelperError> {
+    config.validate()?;
+    config.check_len(s)?;
+    Ok(hash_tokens(&make_tokens(s, config), hasher, config))
+}
+
+/// Same as `generate_hashes`, but this function will also add some random entries to the HashSet
+/// to not expose how many n-grams were actually found. The random entries are drawn from the same range as the real ones.
+pub fn generate_hashes_with_padding<H: BlindIndexHasher + ?Sized, R: Rng + CryptoRng>(
+    s: &str,
+    hasher: &H,
+    config: &IndexConfig,
+    rng: &Mutex<R>,
+) -> Result<HashSet<u64>, SearchHelperError> {
+    let hashes = generate_hashes(s, hasher, config)?;
+    Ok(pad_hashes(
+        hashes,
+        Uniform::new_inclusive(0, config.hash_width.max_value()),
+        config.max_padded_len(config.max_len),
+        rng,
+    ))
+}
+
+/// The hashes to look up in the index for a search term.
+/// These are never padded, since random entries in a query would only cause it to miss.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryHashes {
+    /// The hashes of each token in the search term.
+    pub hashes: HashSet<u64>,
+    /// The number of distinct tokens that were found in the search term.
+    pub token_count: usize,
+    /// False if the search term is likely to match a large part of the index and probably shouldn't be sent as is.
+    /// See `IndexConfig::min_query_tokens` for how n-gram queries are judged.
+    pub is_selective: bool,
+}
+
+/// Make the hashes to search for the string s in an index that was made by `generate_hashes` or `generate_hashes_with_padding`.
+/// The same `hasher` and `config` that were used to build the index must be used here. For `NgramMode::Prefix` indexes
+/// these are not the same hashes `generate_hashes` would give for s.
+/// If the string has more than `config.max_len` characters, this will return an error.
+pub fn generate_query_hashes<H: BlindIndexHasher + ?Sized>(
+    s: &str,
+    hasher: &H,
+    config: &IndexConfig,
+) -> Result<QueryHashes, SearchHelperError> {
+    config.validate()?;
+    config.check_len(s)?;
+    let query = make_query_hashes(
+        &make_query_tokens(s, config),
+        hasher,
+        config,
+        config.min_query_tokens,
+    );
+    if config.ngram_mode == NgramMode::Prefix {
+        let is_selective = index_words(s, config)
             .iter()
-            .map(|tri_gram| short_hash(tri_gram.as_bytes()))
-            .collect();
-        Ok(result)
+            .any(|word| word.chars().count() >= config.ngram_size);
+        Ok(QueryHashes {
+            is_selective,
+            ..query
+        })
+    } else {
+        Ok(query)
     }
 }
 
-/// Generate a version of the input string where each character has been latinized using the
-/// same function as our tokenization routines.
-pub fn transliterate_string(s: &str) -> String {
-    s.chars()
-        .filter(should_keep_char)
-        .map(char_to_trans)
-        .collect()
+/// Make the hashes to search for documents that contain each of the words in s. The index must have been made
+/// with `config.word_tokens` set, otherwise this will return an error. A whole word is far more selective than an
+/// n-gram, so the query is flagged as selective if it has any words at all, rather than by `config.min_query_tokens`.
+/// If the string has more than `config.max_len` characters, this will return an error.
+pub fn generate_word_query_hashes<H: BlindIndexHasher + ?Sized>(
+    s: &str,
+    hasher: &H,
+    config: &IndexConfig,
+) -> Result<QueryHashes, SearchHelperError> {
+    config.validate()?;
+    if !config.word_tokens {
+        return Err(SearchHelperError::InvalidConfiguration(
+            "word_tokens must be set to search for words".to_string(),
+        ));
+    }
+    config.check_len(s)?;
+    Ok(make_query_hashes(
+        &make_word_query_tokens(s, config),
+        hasher,
+        config,
+        1,
+    ))
 }
 
-/// If s is empty, the resulting set will also be empty.
-/// If s is shorter than 3, '-' padding will be added to the end.
-/// All Strings inside of the resulting set will always be of size 3.
-fn make_tri_grams(s: &str) -> HashSet<String> {
-    let converted_string = transliterate_string(s);
-    converted_string
-        .unicode_words()
-        .into_iter()
-        .map(|short_word| {
-            let short_word_len = short_word.chars().count();
-            if short_word_len < 3 {
-                //Pad the short_word with
-                format!("{:-<3}", short_word)
-            } else {
-                short_word.to_string()
-            }
-        })
-        .flat_map(|word| word_to_trigrams(&word))
-        .collect()
+/// The hashes to look up in the index for a typo tolerant search, grouped by the word of the search term they came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuzzyQueryHashes {
+    /// The hashes of the fuzzy tokens of each word in the search term. A word with one typo only shares one hash
+    /// with the word it was meant to be, so a candidate matches if it has at least one hash from every group.
+    pub word_hashes: Vec<HashSet<u64>>,
+}
+
+impl FuzzyQueryHashes {
+    /// Every hash in the search term, to look up in the index.
+    pub fn hashes(&self) -> HashSet<u64> {
+        self.word_hashes.iter().flatten().copied().collect()
+    }
+
+    /// True if the candidate's index entries have at least one hash for every word of the search term.
+    /// A search term with no words never matches.
+    pub fn matches(&self, candidate: &HashSet<u64>) -> bool {
+        !self.word_hashes.is_empty()
+            && self
+                .word_hashes
+                .iter()
+                .all(|word| !word.is_disjoint(candidate))
+    }
+}
+
+/// Make the hashes to search for documents that contain each of the words in s, allowing for a one character typo
+/// (a wrong, missing, extra or swapped character) in each word. Use `FuzzyQueryHashes::matches` to check the
+/// candidates that were found. The index must have been made with `config.fuzzy_tokens` set, otherwise this will
+/// return an error.
+/// If the string has more than `config.max_len` characters, this will return an error.
+pub fn generate_fuzzy_query_hashes<H: BlindIndexHasher + ?Sized>(
+    s: &str,
+    hasher: &H,
+    config: &IndexConfig,
+) -> Result<FuzzyQueryHashes, SearchHelperError> {
+    config.validate()?;
+    if !config.fuzzy_tokens {
+        return Err(SearchHelperError::InvalidConfiguration(
+            "fuzzy_tokens must be set to search with typos".to_string(),
+        ));
+    }
+    config.check_len(s)?;
+    let word_hashes = make_fuzzy_tokens_by_word(s, config)
+        .iter()
+        .map(|tokens| hash_tokens(tokens, hasher, config))
+        .collect();
+    Ok(FuzzyQueryHashes { word_hashes })
+}
+
+/// Make the hashes to search for documents that contain words that sound like each of the words in s.
+/// The index must have been made with `config.phonetic_tokens` set, otherwise this will return an error.
+/// Sound-alike matches are loose, so use `verify_candidate` or the n-gram hashes to rank what this finds.
+/// A single name is still a useful search, so the query is flagged as selective if it has any codes at all, rather than
+/// by `config.min_query_tokens`.
+/// If the string has more than `config.max_len` characters, this will return an error.
+pub fn generate_phonetic_query_hashes<H: BlindIndexHasher + ?Sized>(
+    s: &str,
+    hasher: &H,
+    config: &IndexConfig,
+) -> Result<QueryHashes, SearchHelperError> {
+    config.validate()?;
+    if !config.phonetic_tokens {
+        return Err(SearchHelperError::InvalidConfiguration(
+            "phonetic_tokens must be set to search by sound".to_string(),
+        ));
+    }
+    config.check_len(s)?;
+    Ok(make_query_hashes(
+        &make_phonetic_query_tokens(s, config),
+        hasher,
+        config,
+        1,
+    ))
+}
+
+///Hash the query tokens, flagging the query as not selective if it has fewer than min_tokens of them.
+fn make_query_hashes<H: BlindIndexHasher + ?Sized>(
+    tokens: &HashSet<Token>,
+    hasher: &H,
+    config: &IndexConfig,
+    min_tokens: usize,
+) -> QueryHashes {
+    let hashes = hash_tokens(tokens, hasher, config);
+    //Count the hashes rather than the tokens so a collision in a narrow hash width isn't counted twice.
+    let token_count = hashes.len();
+    QueryHashes {
+        hashes,
+        token_count,
+        is_selective: token_count >= min_tokens,
+    }
+}
+
+/// Make a single hash of the whole string s, for fields such as emails or account numbers that only need exact equality search.
+/// The string is transliterated and filtered with the same rules as `generate_hashes`, and runs of whitespace are
+/// collapsed, so it matches regardless of case, accents and spacing. It is hashed under its own domain tag, so it can never
+/// collide with an n-gram hash, and is truncated to `config.hash_width` bits. If the string has more than `config.max_len`
+/// characters, this will return an error.
+/// The default `CharFilter` removes '@', '.' and other special chars, so "a.b@c.com" and "ab@c.com" get the same hash.
+/// Emails need a `config.char_filter` that keeps them, such as `CharFilter::Deny` of an empty set, which keeps every char.
+pub fn generate_exact_hash<H: BlindIndexHasher + ?Sized>(
+    s: &str,
+    hasher: &H,
+    config: &IndexConfig,
+) -> Result<u64, SearchHelperError> {
+    config.validate()?;
+    config.check_len(s)?;
+    let token = Token::new(TokenDomain::Exact, make_exact_token(s, config));
+    Ok(config
+        .hash_width
+        .truncate(&hasher.hash_token(&token.encode())))
+}
+
+/// Make an index for text that may be far longer than `config.max_len`, such as notes or descriptions.
+/// The text is split on whitespace into chunks of at most `config.max_len` characters and each chunk is broken into n-grams
+/// the same way as `generate_hashes`. A word longer than `config.max_len` is kept whole as a chunk of its own, so every mode
+/// sees the same words it would if the text were indexed in one piece.
+/// The text may have at most `config.max_len * config.max_chunks` characters, otherwise this will return an error.
+pub fn generate_hashes_for_long_text<H: BlindIndexHasher + ?Sized>(
+    s: &str,
+    hasher: &H,
+    config: &IndexConfig,
+) -> Result<HashSet<u64>, SearchHelperError> {
+    config.validate()?;
+    let max_text_len = config.max_len.saturating_mul(config.max_chunks);
+    let actual_len = s.chars().count();
+    if actual_len > max_text_len {
+        return Err(SearchHelperError::InputTooLong {
+            actual_len,
+            max_len: max_text_len,
+        });
+    }
+    let tokens: HashSet<_> = split_into_chunks(s, config.max_len)
+        .iter()
+        .flat_map(|chunk| make_tokens(chunk, config))
+        .collect();
+    Ok(hash_tokens(&tokens, hasher, config))
 }
 
-fn word_to_trigrams(s: &str) -> HashSet<String> {
-    s.chars()
-        .tuple_windows()
-        .map(|(c1, c2, c3)| format!("{}{}{}", c1, c2, c3))
+/// Same as `generate_hashes_for_long_text`, but this function will also add some random entries to the HashSet
+/// to not expose how many n-grams were actually found. The padding is drawn the same way as `generate_hashes_with_padding`
+/// and it is capped the same way, treating the text as if `config.max_len` were `config.max_len * config.max_chunks`.
+pub fn generate_hashes_for_long_text_with_padding<
+    H: BlindIndexHasher + ?Sized,
+    R: Rng + CryptoRng,
+>(
+    s: &str,
+    hasher: &H,
+    config: &IndexConfig,
+    rng: &Mutex<R>,
+) -> Result<HashSet<u64>, SearchHelperError> {
+    let hashes = generate_hashes_for_long_text(s, hasher, config)?;
+    Ok(pad_hashes(
+        hashes,
+        Uniform::new_inclusive(0, config.hash_width.max_value()),
+        config.max_padded_len(config.max_len.saturating_mul(config.max_chunks)),
+        rng,
+    ))
+}
+
+///Hash each of the tokens, truncating them to the configured width.
+fn hash_tokens<H: BlindIndexHasher + ?Sized>(
+    tokens: &HashSet<Token>,
+    hasher: &H,
+    config: &IndexConfig,
+) -> HashSet<u64> {
+    tokens
+        .iter()
+        .map(|token| {
+            config
+                .hash_width
+                .truncate(&hasher.hash_token(&token.encode()))
+        })
         .collect()
 }
 
-///Convert the char if we can, if we can't just create a string out of the character.
-fn char_to_trans(c: char) -> String {
-    let trans_string = unidecode_char(c);
-    if trans_string == "" {
-        format!("{}", c)
-    } else {
-        trans_string.to_lowercase()
+///Split s on whitespace into chunks that have at most max_len chars.
+///A word with more than max_len chars is never split, since the pieces would look like words of their own
+///(with their own prefixes, word boundaries and so on). It becomes a chunk by itself instead.
+fn split_into_chunks(s: &str, max_len: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut current_len = 0;
+    for word in s.split_whitespace() {
+        let word_len = word.chars().count();
+        //Account for the space that will separate this word from the one before it.
+        let needed = if current_len == 0 {
+            word_len
+        } else {
+            word_len + 1
+        };
+        if current_len > 0 && current_len + needed > max_len {
+            chunks.push(std::mem::take(&mut current));
+            current_len = 0;
+        }
+        if current_len > 0 {
+            current.push(' ');
+            current_len += 1;
+        }
+        current.push_str(word);
+        current_len += word_len;
+    }
+    if current_len > 0 {
+        chunks.push(current);
     }
+    chunks
+}
+
+///Add some random entries drawn from padding to hashes so the number of tri-grams that were actually found isn't exposed.
+///The padding will never take hashes past max_len entries, unless they are already there, in which case just one is added.
+fn pad_hashes<T, D, R>(
+    mut hashes: HashSet<T>,
+    padding: D,
+    max_len: usize,
+    rng: &Mutex<R>,
+) -> HashSet<T>
+where
+    T: Eq + Hash,
+    D: Distribution<T>,
+    R: Rng + CryptoRng,
+{
+    let prob = take_lock(rng).deref_mut().sample(*ONE_TO_TWO_HUNDRED);
+    let to_add: u8 = {
+        //Just take the lock once because we need it in all cases and it makes the code look better.
+        let r = &mut *take_lock(rng);
+        if prob <= 1 {
+            r.gen_range(1..200)
+        } else if prob <= 5 {
+            r.gen_range(1..30)
+        } else if prob <= 50 {
+            r.gen_range(1..10)
+        } else {
+            r.gen_range(1..5)
+        }
+    };
+    //For latin input the number of tri-grams is always at least 2 less than max_len, so we're able to pad by at least 2.
+    //Transliteration can expand a character into several (e.g. CJK), so hashes may not fit under max_len. Always pad by
+    //at least one anyway, since an index with no padding would give away exactly how many tokens were found.
+    let pad_len = std::cmp::min(max_len.saturating_sub(hashes.len()), to_add as usize).max(1);
+    hashes.extend(
+        take_lock(rng)
+            .deref_mut()
+            .sample_iter(padding)
+            .take(pad_len),
+    );
+    hashes
+}
+
+///Narrow hashes that were truncated to `HashWidth::Bits32` back down to u32s.
+fn narrow_to_u32(hashes: HashSet<u64>) -> HashSet<u32> {
+    hashes.into_iter().map(|h| h as u32).collect()
+}
+
+/// Generate a version of the input string where each character has been latinized using the
+/// same function as our tokenization routines.
+pub fn transliterate_string(s: &str) -> String {
+    transliterate_with_config(s, &IndexConfig::default())
 }
 
 ///Interpret the most significant 4 bytes as a bigendian u32
@@ -155,7 +671,15 @@ fn as_u32_be(slice: &[u8; 32]) -> u32 {
     ((slice[0] as u32) << 24)
         + ((slice[1] as u32) << 16)
         + ((slice[2] as u32) << 8)
-        + ((slice[3] as u32) << 0)
+        + (slice[3] as u32)
+}
+
+///Interpret the most significant 8 bytes as a bigendian u64
+#[inline]
+fn as_u64_be(slice: &[u8; 32]) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&slice[..8]);
+    u64::from_be_bytes(bytes)
 }
 
 /// Acquire mutex in a blocking fashion. If the Mutex is or becomes poisoned, panic.
@@ -176,7 +700,7 @@ fn as_u32_be(slice: &[u8; 32]) -> u32 {
 /// }; // lock released here
 /// ```
 ///
-fn take_lock<T>(m: &Mutex<T>) -> MutexGuard<T> {
+fn take_lock<T>(m: &Mutex<T>) -> MutexGuard<'_, T> {
     m.lock().unwrap_or_else(|e| {
         let error = format!("Error when acquiring lock: {}", e);
         panic!("{}", error);
@@ -186,13 +710,12 @@ fn take_lock<T>(m: &Mutex<T>) -> MutexGuard<T> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use hmac::{Hmac, Mac};
     use rand::rngs::ThreadRng;
+    use sha2::{Digest, Sha256};
 
-    fn make_set(array: &[&str]) -> HashSet<String> {
-        array
-            .into_iter()
-            .map(|&s| From::from(s))
-            .collect::<HashSet<_>>()
+    fn v2_hasher() -> Sha256Hasher {
+        Sha256Hasher::new(Some("foo"), b"salt", HashVersion::V2).unwrap()
     }
 
     #[test]
@@ -207,6 +730,13 @@ mod tests {
         assert_eq!(result, known_result);
     }
 
+    #[test]
+    fn as_u64_be_known_result() {
+        let mut input = [0u8; 32];
+        input[..9].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(as_u64_be(&input), 0x0102030405060708);
+    }
+
     #[test]
     fn string_transliterated() {
         assert_eq!(transliterate_string("Gumby, dammit!"), "gumby dammit");
@@ -215,116 +745,732 @@ mod tests {
     }
 
     #[test]
-    fn word_to_trigrams_known() {
-        let result = word_to_trigrams("five");
-        assert_eq!(result, make_set(&["fiv", "ive"]));
+    fn generate_hashes_for_string_compute_known_value() -> Result<(), SearchHelperError> {
+        let result = generate_hashes_for_string("123", Some("foo"), &[0u8; 1])?;
+        //We compute this to catch cases where this computation might change.
+        let expected_result = {
+            let mut hasher = Sha256::new();
+            sha2::Digest::update(&mut hasher, "foo".as_bytes());
+            sha2::Digest::update(&mut hasher, [0u8; 1]);
+            sha2::Digest::update(&mut hasher, "123".as_bytes());
+            as_u32_be(&(hasher.finalize().into()))
+        };
+        assert_eq!(result, [expected_result].iter().copied().collect());
+        Ok(())
+    }
+
+    #[test]
+    fn generate_hashes_for_string_versioned_v2_compute_known_value() -> Result<(), SearchHelperError>
+    {
+        let result =
+            generate_hashes_for_string_versioned("123", Some("foo"), &[0u8; 1], HashVersion::V2)?;
+        let expected_result = {
+            let mut hasher = Sha256::new();
+            sha2::Digest::update(&mut hasher, b"ironcore-search-helpers/v2");
+            sha2::Digest::update(&mut hasher, [1u8]);
+            sha2::Digest::update(&mut hasher, 3u64.to_be_bytes());
+            sha2::Digest::update(&mut hasher, "foo".as_bytes());
+            sha2::Digest::update(&mut hasher, 1u64.to_be_bytes());
+            sha2::Digest::update(&mut hasher, [0u8; 1]);
+            sha2::Digest::update(&mut hasher, 3u64.to_be_bytes());
+            sha2::Digest::update(&mut hasher, "123".as_bytes());
+            as_u32_be(&(hasher.finalize().into()))
+        };
+        assert_eq!(result, [expected_result].iter().copied().collect());
+        Ok(())
+    }
+
+    #[test]
+    fn generate_hashes_for_string_versioned_v1_matches_unversioned() -> Result<(), SearchHelperError>
+    {
+        assert_eq!(
+            generate_hashes_for_string_versioned("José", Some("foo"), b"salt", HashVersion::V1)?,
+            generate_hashes_for_string("José", Some("foo"), b"salt")?
+        );
+        Ok(())
     }
 
     #[test]
-    fn make_tri_grams_works_multi_word() {
+    fn generate_hashes_for_string_versioned_v2_separates_partition_and_salt(
+    ) -> Result<(), SearchHelperError> {
+        //V1 can't tell these apart, which is the reason V2 exists.
         assert_eq!(
-            make_tri_grams("123 José  Núñez 812-111-7654"),
-            make_set(&[
-                "123", "jos", "ose", "nun", "une", "nez", "812", "121", "211", "111", "117", "176",
-                "765", "654",
-            ])
+            generate_hashes_for_string_versioned("123", Some("ab"), b"c", HashVersion::V1)?,
+            generate_hashes_for_string_versioned("123", Some("a"), b"bc", HashVersion::V1)?
+        );
+        assert_ne!(
+            generate_hashes_for_string_versioned("123", Some("ab"), b"c", HashVersion::V2)?,
+            generate_hashes_for_string_versioned("123", Some("a"), b"bc", HashVersion::V2)?
+        );
+        assert_ne!(
+            generate_hashes_for_string_versioned("123", None, b"salt", HashVersion::V2)?,
+            generate_hashes_for_string_versioned("123", Some(""), b"salt", HashVersion::V2)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn generate_hashes_32_bits_matches_versioned() -> Result<(), SearchHelperError> {
+        let narrow =
+            generate_hashes_for_string_versioned("José", Some("foo"), b"salt", HashVersion::V2)?;
+        let wide = generate_hashes("José", &v2_hasher(), &IndexConfig::default())?;
+        assert_eq!(wide, narrow.into_iter().map(|h| h as u64).collect());
+        Ok(())
+    }
+
+    #[test]
+    fn generate_hashes_truncates_most_significant_bits() -> Result<(), SearchHelperError> {
+        let hasher = Sha256Hasher::new(Some("foo"), b"salt", HashVersion::V1)?;
+        let with_width = |hash_width| {
+            let config = IndexConfig {
+                hash_width,
+                ..IndexConfig::default()
+            };
+            generate_hashes("123", &hasher, &config)
+        };
+        let bits_32 = with_width(HashWidth::Bits32)?;
+        let bits_48 = with_width(HashWidth::Bits48)?;
+        let bits_64 = with_width(HashWidth::Bits64)?;
+        let shifted = |hashes: &HashSet<u64>, by: u32| -> HashSet<u64> {
+            hashes.iter().map(|h| h >> by).collect()
+        };
+        assert_eq!(shifted(&bits_64, 16), bits_48);
+        assert_eq!(shifted(&bits_64, 32), bits_32);
+        Ok(())
+    }
+
+    #[test]
+    fn generate_hashes_with_padding_stays_in_width() -> Result<(), SearchHelperError> {
+        let rng = Mutex::new(ThreadRng::default());
+        let config = IndexConfig {
+            hash_width: HashWidth::Bits48,
+            ..IndexConfig::default()
+        };
+        let result = generate_hashes_with_padding("123", &v2_hasher(), &config, &rng)?;
+        assert!(result.len() > 1);
+        assert!(result.iter().all(|h| *h < 1 << 48));
+        Ok(())
+    }
+
+    #[test]
+    fn generate_hmac_hashes_for_string_compute_known_value() -> Result<(), SearchHelperError> {
+        let result = generate_hmac_hashes_for_string("123", Some("foo"), b"key")?;
+        let expected_result = {
+            let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(b"key").unwrap();
+            Mac::update(&mut mac, &[1u8]);
+            Mac::update(&mut mac, &3u64.to_be_bytes());
+            Mac::update(&mut mac, "foo".as_bytes());
+            Mac::update(&mut mac, &3u64.to_be_bytes());
+            Mac::update(&mut mac, "123".as_bytes());
+            as_u32_be(&mac.finalize().into_bytes().into())
+        };
+        assert_eq!(result, [expected_result].iter().copied().collect());
+        Ok(())
+    }
+
+    #[test]
+    fn generate_hmac_hashes_for_string_depends_on_key() -> Result<(), SearchHelperError> {
+        assert_ne!(
+            generate_hmac_hashes_for_string("José", Some("foo"), b"key1")?,
+            generate_hmac_hashes_for_string("José", Some("foo"), b"key2")?
         );
+        Ok(())
     }
 
     #[test]
-    fn make_tri_grams_works_non_ascii() {
+    fn generate_hmac_hashes_for_string_with_padding_adds_at_least_one(
+    ) -> Result<(), SearchHelperError> {
+        let rng = Mutex::new(ThreadRng::default());
+        let unpadded = generate_hmac_hashes_for_string("123", Some("foo"), b"key")?;
+        let result =
+            generate_hmac_hashes_for_string_with_padding("123", Some("foo"), b"key", &rng)?;
+        assert!(result.len() > 1);
+        assert!(result.is_superset(&unpadded));
+        Ok(())
+    }
+
+    #[test]
+    fn generate_hashes_differs_by_hasher() -> Result<(), SearchHelperError> {
+        let config = IndexConfig {
+            hash_width: HashWidth::Bits64,
+            ..IndexConfig::default()
+        };
+        let with_hasher = |hasher: &dyn BlindIndexHasher| generate_hashes("José", hasher, &config);
+        let results = [
+            with_hasher(&v2_hasher())?,
+            with_hasher(&HmacSha256Hasher::new(Some("foo"), b"salt")?)?,
+            with_hasher(&Sha512_256Hasher::new(Some("foo"), b"salt")?)?,
+        ];
+        for (i, result) in results.iter().enumerate() {
+            assert_eq!(result.len(), 2);
+            assert!(results[i + 1..].iter().all(|other| other != result));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn generate_hashes_for_string_with_padding_adds_at_least_one() -> Result<(), SearchHelperError>
+    {
+        let rng = Mutex::new(ThreadRng::default());
+        let result = generate_hashes_for_string_with_padding("123", Some("foo"), &[0u8; 1], &rng)?;
+        assert!(result.len() > 1);
+        Ok(())
+    }
+
+    #[test]
+    fn generate_hashes_for_string_with_padding_versioned_adds_at_least_one(
+    ) -> Result<(), SearchHelperError> {
+        let rng = Mutex::new(ThreadRng::default());
+        let unpadded =
+            generate_hashes_for_string_versioned("123", Some("foo"), b"salt", HashVersion::V2)?;
+        let result = generate_hashes_for_string_with_padding_versioned(
+            "123",
+            Some("foo"),
+            b"salt",
+            HashVersion::V2,
+            &rng,
+        )?;
+        assert!(result.len() > 1);
+        assert!(result.is_superset(&unpadded));
+        Ok(())
+    }
+
+    #[test]
+    fn generate_hashes_for_string_with_padding_empty_string() -> Result<(), SearchHelperError> {
+        let rng = Mutex::new(ThreadRng::default());
+        let result = generate_hashes_for_string_with_padding("", Some("foo"), &[0u8; 1], &rng)?;
+        assert!(!result.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn generate_hashes_for_string_counts_chars_not_bytes() -> Result<(), SearchHelperError> {
+        //70 chars, but 210 bytes.
+        let input = "北".repeat(70);
+        generate_hashes_for_string(&input, Some("foo"), &[0u8; 1])?;
+        Ok(())
+    }
+
+    #[test]
+    fn generate_hashes_respects_configured_max_len() -> Result<(), SearchHelperError> {
+        let hasher = v2_hasher();
+        let config = IndexConfig {
+            max_len: 5,
+            ..IndexConfig::default()
+        };
         assert_eq!(
-            make_tri_grams("TİRYAKİ"),
-            make_set(&["tir", "iry", "rya", "yak", "aki"])
+            generate_hashes("abcdef", &hasher, &config).unwrap_err(),
+            SearchHelperError::InputTooLong {
+                actual_len: 6,
+                max_len: 5
+            }
         );
+        assert_eq!(generate_hashes("abcde", &hasher, &config)?.len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn generate_hashes_with_padding_stays_under_configured_max_len() -> Result<(), SearchHelperError>
+    {
+        let rng = Mutex::new(ThreadRng::default());
+        let hasher = v2_hasher();
+        let config = IndexConfig {
+            max_len: 5,
+            ..IndexConfig::default()
+        };
+        for _ in 0..50 {
+            let result = generate_hashes_with_padding("ab cd", &hasher, &config, &rng)?;
+            assert!(result.len() > 2 && result.len() <= 5);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn generate_hashes_with_padding_pads_long_inputs() -> Result<(), SearchHelperError> {
+        let rng = Mutex::new(ThreadRng::default());
+        let hasher = v2_hasher();
+        let fuzzy = IndexConfig {
+            fuzzy_tokens: true,
+            word_tokens: true,
+            ..IndexConfig::default()
+        };
+        let dual = IndexConfig {
+            transliteration: TransliterationProfile::Dual,
+            ..IndexConfig::default()
+        };
+        //17 different words of 10 characters each, with few n-grams in common.
+        let words = |alphabet: &str| -> String {
+            let chars: Vec<char> = alphabet.chars().collect();
+            (0..17)
+                .map(|i| {
+                    (0..10)
+                        .map(|j| chars[(i * 7 + j * j * 3 + j) % chars.len()])
+                        .collect::<String>()
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let latin = words("abcdefghijklmnopqrstuvwxyz");
+        let cyrillic = words("абвгдежзийклмнопрстуфхцчшщ");
+        for &(s, config) in [(latin.as_str(), &fuzzy), (cyrillic.as_str(), &dual)].iter() {
+            let unpadded = generate_hashes(s, &hasher, config)?.len();
+            assert!(unpadded > config.max_len);
+            for _ in 0..50 {
+                let padded = generate_hashes_with_padding(s, &hasher, config, &rng)?.len();
+                assert!(padded > unpadded && padded <= config.max_padded_len(config.max_len));
+            }
+        }
+        Ok(())
     }
 
     #[test]
-    fn make_tri_grams_eliminates_duplicates() {
+    fn generate_hashes_rejects_zero_max_len() -> Result<(), SearchHelperError> {
+        let hasher = v2_hasher();
+        let config = IndexConfig {
+            max_len: 0,
+            ..IndexConfig::default()
+        };
+        assert!(matches!(
+            generate_hashes("", &hasher, &config),
+            Err(SearchHelperError::InvalidConfiguration(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn generate_hashes_rejects_stemmed_prefixes() -> Result<(), SearchHelperError> {
+        let hasher = v2_hasher();
+        let config = IndexConfig {
+            stem_words: true,
+            ngram_mode: NgramMode::Prefix,
+            ..IndexConfig::default()
+        };
+        assert!(matches!(
+            generate_hashes("running", &hasher, &config),
+            Err(SearchHelperError::InvalidConfiguration(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn split_into_chunks_known() {
+        assert_eq!(
+            split_into_chunks("the quick  brown fox", 10),
+            vec!["the quick", "brown fox"]
+        );
         assert_eq!(
-            make_tri_grams("TİRYAKİ TİRYAKİ"),
-            make_set(&["tir", "iry", "rya", "yak", "aki"])
+            split_into_chunks("ab cdefgh ij", 5),
+            vec!["ab", "cdefgh", "ij"]
         );
+        assert!(split_into_chunks("  ", 5).is_empty());
     }
 
     #[test]
-    fn make_tri_grams_works_short_non_ascii() {
-        assert_eq!(make_tri_grams("Tİ"), make_set(&["ti-"]));
+    fn generate_hashes_for_long_text_matches_short_text() -> Result<(), SearchHelperError> {
+        let hasher = v2_hasher();
+        let config = IndexConfig::default();
+        let short = "123 José  Núñez 812-111-7654";
+        assert_eq!(
+            generate_hashes_for_long_text(short, &hasher, &config)?,
+            generate_hashes(short, &hasher, &config)?
+        );
+        Ok(())
     }
 
     #[test]
-    fn make_tri_grams_works_multichar_translate() {
+    fn generate_hashes_for_long_text_keeps_all_tri_grams() -> Result<(), SearchHelperError> {
+        let hasher = v2_hasher();
+        let small_chunks = IndexConfig {
+            max_len: 4,
+            ..IndexConfig::default()
+        };
+        let input = "abcdefghij klmno pq";
         assert_eq!(
-            make_tri_grams("志    豪 İ"),
-            make_set(&["zhi", "hao", "i--"])
+            generate_hashes_for_long_text(input, &hasher, &small_chunks)?,
+            generate_hashes(input, &hasher, &IndexConfig::default())?
         );
+        Ok(())
     }
 
     #[test]
-    fn make_tri_grams_works_arabic() {
+    fn generate_hashes_for_long_text_keeps_long_words_whole() -> Result<(), SearchHelperError> {
+        let hasher = v2_hasher();
+        let long_word = "ab".repeat(125);
+        let input = format!("x {} y", long_word);
+        for &ngram_mode in [NgramMode::All, NgramMode::Prefix, NgramMode::Anchored].iter() {
+            let config = IndexConfig {
+                ngram_mode,
+                word_tokens: true,
+                phonetic_tokens: true,
+                fuzzy_tokens: true,
+                ..IndexConfig::default()
+            };
+            let whole = IndexConfig {
+                max_len: 300,
+                ..config.clone()
+            };
+            assert_eq!(
+                generate_hashes_for_long_text(&input, &hasher, &config)?,
+                generate_hashes(&input, &hasher, &whole)?
+            );
+        }
+        let prefix = IndexConfig {
+            ngram_mode: NgramMode::Prefix,
+            ..IndexConfig::default()
+        };
+        let index = generate_hashes_for_long_text(&input, &hasher, &prefix)?;
+        let query = generate_query_hashes("ba", &hasher, &prefix)?;
+        assert!(!query.hashes.is_subset(&index));
+        Ok(())
+    }
+
+    #[test]
+    fn generate_hashes_for_long_text_accepts_more_than_max_len() -> Result<(), SearchHelperError> {
+        let hasher = v2_hasher();
+        let config = IndexConfig::default();
+        let input = "lorem ipsum dolor sit amet ".repeat(20);
+        assert!(generate_hashes(&input, &hasher, &config).is_err());
+        assert!(!generate_hashes_for_long_text(&input, &hasher, &config)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn generate_hashes_for_long_text_too_long_errors() -> Result<(), SearchHelperError> {
+        let hasher = v2_hasher();
+        let config = IndexConfig {
+            max_len: 10,
+            max_chunks: 2,
+            ..IndexConfig::default()
+        };
         assert_eq!(
-            make_tri_grams("شريط فو"),
-            make_set(&["shr", "hry", "ryt", "fw-"])
+            generate_hashes_for_long_text(&"a".repeat(21), &hasher, &config).unwrap_err(),
+            SearchHelperError::InputTooLong {
+                actual_len: 21,
+                max_len: 20
+            }
         );
+        Ok(())
+    }
+
+    #[test]
+    fn generate_hashes_for_long_text_with_padding_adds_at_least_one(
+    ) -> Result<(), SearchHelperError> {
+        let rng = Mutex::new(ThreadRng::default());
+        let hasher = v2_hasher();
+        let config = IndexConfig::default();
+        let input = "lorem ipsum dolor sit amet ".repeat(20);
+        let unpadded = generate_hashes_for_long_text(&input, &hasher, &config)?;
+        let result = generate_hashes_for_long_text_with_padding(&input, &hasher, &config, &rng)?;
+        assert!(result.len() > unpadded.len());
+        assert!(result.is_superset(&unpadded));
+        Ok(())
+    }
+
+    #[test]
+    fn generate_query_hashes_matches_unpadded_index() -> Result<(), SearchHelperError> {
+        let hasher = v2_hasher();
+        let config = IndexConfig::default();
+        let query = generate_query_hashes("José", &hasher, &config)?;
+        assert_eq!(query.hashes, generate_hashes("José", &hasher, &config)?);
+        assert_eq!(query.token_count, 2);
+        assert!(query.is_selective);
+        Ok(())
+    }
+
+    #[test]
+    fn generate_query_hashes_flags_short_terms() -> Result<(), SearchHelperError> {
+        let hasher = v2_hasher();
+        let config = IndexConfig::default();
+        let query = generate_query_hashes("jo", &hasher, &config)?;
+        assert_eq!(query.token_count, 1);
+        assert!(!query.is_selective);
+        let empty = generate_query_hashes("", &hasher, &config)?;
+        assert!(empty.hashes.is_empty());
+        assert!(!empty.is_selective);
+        Ok(())
     }
+
+    #[test]
+    fn generate_query_hashes_prefix_judges_word_length() -> Result<(), SearchHelperError> {
+        let hasher = v2_hasher();
+        let config = IndexConfig {
+            ngram_mode: NgramMode::Prefix,
+            ..IndexConfig::default()
+        };
+        let whole_word = generate_query_hashes("josephine", &hasher, &config)?;
+        assert_eq!(whole_word.token_count, 1);
+        assert!(whole_word.is_selective);
+        assert!(!generate_query_hashes("j", &hasher, &config)?.is_selective);
+        assert!(!generate_query_hashes("j s", &hasher, &config)?.is_selective);
+        assert!(!generate_query_hashes("", &hasher, &config)?.is_selective);
+        Ok(())
+    }
+
+    #[test]
+    fn generate_hashes_uses_configured_ngram_size() -> Result<(), SearchHelperError> {
+        let hasher = v2_hasher();
+        let bigrams = IndexConfig {
+            ngram_size: 2,
+            ..IndexConfig::default()
+        };
+        let expected: HashSet<u64> = ["jo", "os", "se"]
+            .iter()
+            .map(|bigram| as_u32_be(&hasher.hash_token(bigram.as_bytes())) as u64)
+            .collect();
+        assert_eq!(generate_hashes("José", &hasher, &bigrams)?, expected);
+        Ok(())
+    }
+
     #[test]
-    fn make_tri_grams_works_short_multibyte() {
+    fn generate_hashes_rejects_unigrams() -> Result<(), SearchHelperError> {
+        let hasher = v2_hasher();
+        let config = IndexConfig {
+            ngram_size: 1,
+            ..IndexConfig::default()
+        };
+        assert!(matches!(
+            generate_hashes("José", &hasher, &config),
+            Err(SearchHelperError::InvalidConfiguration(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn generate_hashes_for_long_text_keeps_all_five_grams() -> Result<(), SearchHelperError> {
+        let hasher = v2_hasher();
+        let small_chunks = IndexConfig {
+            max_len: 6,
+            ngram_size: 5,
+            ..IndexConfig::default()
+        };
+        let input = "abcdefghijklmn opqrs tu";
         assert_eq!(
-            make_tri_grams("\u{102AE}\u{102AF}"),
-            make_set(&["\u{102AE}\u{102AF}-"])
+            generate_hashes_for_long_text(input, &hasher, &small_chunks)?,
+            generate_hashes(
+                input,
+                &hasher,
+                &IndexConfig {
+                    ngram_size: 5,
+                    ..IndexConfig::default()
+                }
+            )?
         );
+        Ok(())
+    }
+
+    #[test]
+    fn generate_query_hashes_prefix_mode_finds_typeahead() -> Result<(), SearchHelperError> {
+        let hasher = v2_hasher();
+        let config = IndexConfig {
+            ngram_mode: NgramMode::Prefix,
+            ..IndexConfig::default()
+        };
+        let index = generate_hashes("José Núñez", &hasher, &config)?;
+        let query = generate_query_hashes("Nu jos", &hasher, &config)?;
+        assert_eq!(query.token_count, 2);
+        assert!(query.hashes.is_subset(&index));
+        let miss = generate_query_hashes("ose", &hasher, &config)?;
+        assert!(!miss.hashes.is_subset(&index));
+        Ok(())
     }
 
     #[test]
-    fn char_to_trans_latinizable() {
-        assert_eq!(char_to_trans('İ'), "i")
+    fn generate_query_hashes_anchored_mode_ranks_word_starts_higher(
+    ) -> Result<(), SearchHelperError> {
+        let hasher = v2_hasher();
+        let config = IndexConfig {
+            ngram_mode: NgramMode::Anchored,
+            ..IndexConfig::default()
+        };
+        let query = generate_query_hashes("jose", &hasher, &config)?;
+        let word_start = generate_hashes("José Núñez", &hasher, &config)?;
+        let mid_word = generate_hashes("Ajose", &hasher, &config)?;
+        let word_start_score = score_candidate(&query.hashes, &word_start);
+        let mid_word_score = score_candidate(&query.hashes, &mid_word);
+        assert_eq!(word_start_score.coverage, 1.0);
+        assert_eq!(mid_word_score.coverage, 0.75);
+        Ok(())
     }
 
     #[test]
-    fn char_to_trans_not_latinizable() {
-        let c = "\u{102AE}".chars().nth(0).unwrap();
-        assert_eq!(char_to_trans(c), "\u{102AE}")
+    fn generate_exact_hash_normalizes() -> Result<(), SearchHelperError> {
+        let hasher = v2_hasher();
+        let config = IndexConfig::default();
+        assert_eq!(
+            generate_exact_hash("José  Núñez", &hasher, &config)?,
+            generate_exact_hash("jose nunez!", &hasher, &config)?
+        );
+        assert_ne!(
+            generate_exact_hash("José Núñez", &hasher, &config)?,
+            generate_exact_hash("José Núñe", &hasher, &config)?
+        );
+        Ok(())
     }
+
     #[test]
-    fn generate_hashes_for_string_compute_known_value() -> Result<(), String> {
-        let result = generate_hashes_for_string("123", Some("foo"), &[0u8; 1])?;
-        //We compute this to catch cases where this computation might change.
-        let expected_result = {
-            let mut hasher = Sha256::new();
-            sha2::Digest::update(&mut hasher, "foo".as_bytes());
-            sha2::Digest::update(&mut hasher, [0u8; 1]);
-            sha2::Digest::update(&mut hasher, "123".as_bytes());
-            as_u32_be(&(hasher.finalize().into()))
+    fn generate_exact_hash_emails_need_char_filter() -> Result<(), SearchHelperError> {
+        let hasher = v2_hasher();
+        let default = IndexConfig::default();
+        assert_eq!(
+            generate_exact_hash("a.b@c.com", &hasher, &default)?,
+            generate_exact_hash("ab@c.com", &hasher, &default)?
+        );
+        let keep_all = IndexConfig {
+            char_filter: CharFilter::Deny(HashSet::new()),
+            ..IndexConfig::default()
         };
-        assert_eq!(result, [expected_result].iter().map(|x| *x).collect());
+        assert_ne!(
+            generate_exact_hash("a.b@c.com", &hasher, &keep_all)?,
+            generate_exact_hash("ab@c.com", &hasher, &keep_all)?
+        );
+        assert_eq!(
+            generate_exact_hash("A.B@c.com", &hasher, &keep_all)?,
+            generate_exact_hash("a.b@C.COM", &hasher, &keep_all)?
+        );
+        assert_ne!(
+            generate_exact_hash("a+b@c.com", &hasher, &keep_all)?,
+            generate_exact_hash("a-b@c.com", &hasher, &keep_all)?
+        );
+        assert_ne!(
+            generate_exact_hash("a.b@c.com", &hasher, &keep_all)?,
+            generate_exact_hash("a.b c.com", &hasher, &keep_all)?
+        );
+        assert_ne!(
+            generate_exact_hash("john@x.com", &hasher, &keep_all)?,
+            generate_exact_hash("john#x.com", &hasher, &keep_all)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn generate_exact_hash_never_matches_ngram_hash() -> Result<(), SearchHelperError> {
+        let hasher = Sha256Hasher::new(Some("foo"), b"salt", HashVersion::V1)?;
+        let config = IndexConfig::default();
+        //"abc" is both the whole value and its only tri-gram.
+        let ngram_hashes = generate_hashes("abc", &hasher, &config)?;
+        let exact_hash = generate_exact_hash("abc", &hasher, &config)?;
+        assert_eq!(ngram_hashes.len(), 1);
+        assert!(!ngram_hashes.contains(&exact_hash));
         Ok(())
     }
 
     #[test]
-    fn generate_hashes_for_string_with_padding_adds_at_least_one() -> Result<(), String> {
+    fn generate_word_query_hashes_matches_whole_words() -> Result<(), SearchHelperError> {
+        let hasher = v2_hasher();
+        let config = IndexConfig {
+            word_tokens: true,
+            ..IndexConfig::default()
+        };
         let rng = Mutex::new(ThreadRng::default());
-        let result = generate_hashes_for_string_with_padding("123", Some("foo"), &[0u8; 1], &rng)?;
-        assert!(result.len() > 1);
+        let index = generate_hashes_with_padding("123 José  Núñez", &hasher, &config, &rng)?;
+        let ngrams = generate_query_hashes("José Núñez", &hasher, &config)?;
+        let words = generate_word_query_hashes("jose NUNEZ", &hasher, &config)?;
+        assert_eq!(words.token_count, 2);
+        assert!(ngrams.hashes.is_subset(&index));
+        assert!(words.hashes.is_subset(&index));
+        assert!(ngrams.hashes.is_disjoint(&words.hashes));
+        let partial = generate_word_query_hashes("jos", &hasher, &config)?;
+        assert!(!partial.hashes.is_subset(&index));
+        let one_word = generate_word_query_hashes("smithers", &hasher, &config)?;
+        assert_eq!(one_word.token_count, 1);
+        assert!(one_word.is_selective);
+        assert!(!generate_word_query_hashes("", &hasher, &config)?.is_selective);
         Ok(())
     }
 
     #[test]
-    fn generate_hashes_for_string_with_padding_empty_string() -> Result<(), String> {
+    fn generate_phonetic_query_hashes_matches_sound_alikes() -> Result<(), SearchHelperError> {
+        let hasher = v2_hasher();
+        let config = IndexConfig {
+            phonetic_tokens: true,
+            ..IndexConfig::default()
+        };
+        let index = generate_hashes("John Smith", &hasher, &config)?;
+        let query = generate_phonetic_query_hashes("Jon Smyth", &hasher, &config)?;
+        assert_eq!(query.token_count, 2);
+        assert!(query.hashes.is_subset(&index));
+        let other = generate_phonetic_query_hashes("Mary", &hasher, &config)?;
+        assert!(!other.hashes.is_subset(&index));
+        assert!(other.is_selective);
+        assert!(!generate_phonetic_query_hashes("42", &hasher, &config)?.is_selective);
+        assert!(matches!(
+            generate_phonetic_query_hashes("Jon", &hasher, &IndexConfig::default()),
+            Err(SearchHelperError::InvalidConfiguration(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn generate_fuzzy_query_hashes_tolerates_typos() -> Result<(), SearchHelperError> {
+        let hasher = v2_hasher();
+        let config = IndexConfig {
+            fuzzy_tokens: true,
+            ..IndexConfig::default()
+        };
         let rng = Mutex::new(ThreadRng::default());
-        let result = generate_hashes_for_string_with_padding("", Some("foo"), &[0u8; 1], &rng)?;
-        assert!(result.len() >= 1);
+        let index = generate_hashes_with_padding("John Smith", &hasher, &config, &rng)?;
+        let finds = |query| -> Result<bool, SearchHelperError> {
+            Ok(generate_fuzzy_query_hashes(query, &hasher, &config)?.matches(&index))
+        };
+        assert!(finds("jhon smith")?);
+        assert!(finds("john smyth")?);
+        assert!(finds("smth")?);
+        assert!(!finds("jane")?);
+        assert!(!finds("")?);
+        assert!(matches!(
+            generate_fuzzy_query_hashes("jhon", &hasher, &IndexConfig::default()),
+            Err(SearchHelperError::InvalidConfiguration(_))
+        ));
         Ok(())
     }
 
     #[test]
-    fn generate_hashes_for_string_too_long_errors() -> Result<(), String> {
+    fn generate_fuzzy_query_hashes_needs_every_word() -> Result<(), SearchHelperError> {
+        let hasher = v2_hasher();
+        let config = IndexConfig {
+            fuzzy_tokens: true,
+            ..IndexConfig::default()
+        };
+        let index = generate_hashes("John Smith", &hasher, &config)?;
+        let query = generate_fuzzy_query_hashes("john zzzzzz", &hasher, &config)?;
+        assert_eq!(query.word_hashes.len(), 2);
+        assert!(!query.hashes().is_disjoint(&index));
+        assert!(!query.matches(&index));
+        assert!(!generate_fuzzy_query_hashes("john qqq", &hasher, &config)?.matches(&index));
+        assert!(!generate_fuzzy_query_hashes("jhon qqq", &hasher, &config)?.matches(&index));
+        Ok(())
+    }
+
+    #[test]
+    fn generate_word_query_hashes_requires_word_tokens() -> Result<(), SearchHelperError> {
+        let hasher = v2_hasher();
+        assert!(matches!(
+            generate_word_query_hashes("jose", &hasher, &IndexConfig::default()),
+            Err(SearchHelperError::InvalidConfiguration(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn generate_hashes_for_string_too_long_errors() -> Result<(), SearchHelperError> {
         let rng = ThreadRng::default();
         let input: Vec<u8> = rng
             .sample_iter(rand::distributions::Alphanumeric)
             .take(201)
             .collect();
-        generate_hashes_for_string(std::str::from_utf8(&input).unwrap(), Some("foo"), &[0u8; 1])
-            .unwrap_err();
+        let err = generate_hashes_for_string(
+            std::str::from_utf8(&input).unwrap(),
+            Some("foo"),
+            &[0u8; 1],
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            SearchHelperError::InputTooLong {
+                actual_len: 201,
+                max_len: 200
+            }
+        );
         Ok(())
     }
 }
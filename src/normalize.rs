@@ -0,0 +1,142 @@
+use caseless::Caseless;
+use std::borrow::Cow;
+use unicode_normalization::UnicodeNormalization;
+
+const COMBINING_DOT_ABOVE: char = '\u{307}';
+
+/// How an input is normalized before its characters are filtered and transliterated.
+/// Changing this changes the hashes, so an index must always be queried with the profile it was built with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NormalizationProfile {
+    /// No normalization, so composed and decomposed forms of the same text (or fullwidth and ASCII digits) can end up
+    /// with different hashes.
+    V1,
+    /// Unicode compatibility caseless normalization (NFKC with full case folding), so text that looks the same
+    /// always gives the same hashes.
+    V2,
+}
+
+impl Default for NormalizationProfile {
+    fn default() -> NormalizationProfile {
+        NormalizationProfile::V1
+    }
+}
+
+impl NormalizationProfile {
+    pub(crate) fn normalize(self, s: &str) -> Cow<'_, str> {
+        match self {
+            NormalizationProfile::V1 => Cow::Borrowed(s),
+            //This is the compatibility caseless match from the Unicode standard, composed again at the end.
+            NormalizationProfile::V2 => {
+                let mut normalized = String::with_capacity(s.len());
+                for c in s
+                    .nfd()
+                    .default_case_fold()
+                    .nfkd()
+                    .default_case_fold()
+                    .nfkc()
+                {
+                    //Folding 'İ' gives "i\u{307}", and that dot has nothing to compose with, so it is dropped to keep
+                    //"İstanbul" and "Istanbul" the same. A dot on any other letter is left alone.
+                    if !(c == COMBINING_DOT_ABOVE && normalized.ends_with('i')) {
+                        normalized.push(c);
+                    }
+                }
+                Cow::Owned(normalized)
+            }
+        }
+    }
+}
+
+/// How characters are transliterated to Latin before they are tokenized.
+/// An index built with a locale profile (or `Dual`) also holds the tokens from the standard transliteration, so a query
+/// written either way (e.g. "Müller", "Mueller" or "Muller") finds it. Queries only use the profile's own rules.
+/// Turkish needs no profile of its own, since the standard transliteration already makes the dotted and dotless i
+/// ('İ', 'I', 'ı' and 'i') the same letter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TransliterationProfile {
+    /// Only the standard transliteration.
+    Standard,
+    /// 'ä', 'ö' and 'ü' become "ae", "oe" and "ue", and 'ß' becomes "ss".
+    German,
+    /// 'å' becomes "aa", 'æ' and 'ä' become "ae", and 'ø' and 'ö' become "oe".
+    Scandinavian,
+    /// No transliteration. Characters are only lowercased, so text is indexed and searched in its own script and
+    /// unrelated words that romanize the same way (e.g. Arabic "شريط" and others that become "shryt") stay apart.
+    Native,
+    /// The same as `Native`, but the index also holds the standard transliteration, so mixed-language data can be
+    /// searched in either form: "Москва" is found by "Москва" or "moskva", and "José" by "José" or "jose".
+    Dual,
+}
+
+impl Default for TransliterationProfile {
+    fn default() -> TransliterationProfile {
+        TransliterationProfile::Standard
+    }
+}
+
+impl TransliterationProfile {
+    ///True for the profiles whose indexes also hold the standard transliteration.
+    pub(crate) fn also_indexes_standard(self) -> bool {
+        match self {
+            TransliterationProfile::German
+            | TransliterationProfile::Scandinavian
+            | TransliterationProfile::Dual => true,
+            TransliterationProfile::Standard | TransliterationProfile::Native => false,
+        }
+    }
+
+    ///True for the profiles that keep characters in their own script.
+    pub(crate) fn keeps_script(self) -> bool {
+        self == TransliterationProfile::Native || self == TransliterationProfile::Dual
+    }
+
+    ///The locale's transliteration of c, if it has its own rule for it.
+    pub(crate) fn transliterate(self, c: char) -> Option<&'static str> {
+        match self {
+            TransliterationProfile::Standard
+            | TransliterationProfile::Native
+            | TransliterationProfile::Dual => None,
+            TransliterationProfile::German => match c {
+                'ä' | 'Ä' => Some("ae"),
+                'ö' | 'Ö' => Some("oe"),
+                'ü' | 'Ü' => Some("ue"),
+                'ß' | 'ẞ' => Some("ss"),
+                _ => None,
+            },
+            TransliterationProfile::Scandinavian => match c {
+                'å' | 'Å' => Some("aa"),
+                'æ' | 'Æ' | 'ä' | 'Ä' => Some("ae"),
+                'ø' | 'Ø' | 'ö' | 'Ö' => Some("oe"),
+                _ => None,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_v1_is_unchanged() {
+        assert_eq!(
+            NormalizationProfile::V1.normalize("E\u{301}１"),
+            "E\u{301}１"
+        );
+    }
+
+    #[test]
+    fn normalize_v2_known() {
+        let normalize = |s| NormalizationProfile::V2.normalize(s).into_owned();
+        assert_eq!(normalize("E\u{301}"), "\u{e9}");
+        assert_eq!(normalize("\u{c9}"), "\u{e9}");
+        assert_eq!(normalize("１２３"), "123");
+        assert_eq!(normalize("Straße"), "strasse");
+        assert_eq!(normalize("ΟΔΟΣ"), normalize("οδος"));
+        assert_eq!(normalize("İstanbul"), "istanbul");
+        //Dots that compose, or that are on a letter other than 'i', are kept.
+        assert_eq!(normalize("Z\u{307}"), "\u{17c}");
+        assert_eq!(normalize("Q\u{307}x"), "q\u{307}x");
+    }
+}
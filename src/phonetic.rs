@@ -0,0 +1,73 @@
+///The American Soundex code for word, e.g. "r163" for both "robert" and "rupert".
+///Only ASCII letters are coded, so this expects a word that has already been through `transliterate_string`.
+///Returns None if the word has no letters to code.
+pub(crate) fn soundex(word: &str) -> Option<String> {
+    let mut letters = word
+        .chars()
+        .filter(char::is_ascii_alphabetic)
+        .map(|c| c.to_ascii_lowercase());
+    let first = letters.next()?;
+    let mut code = first.to_string();
+    let mut last_digit = soundex_digit(first);
+    for c in letters {
+        //'h' and 'w' don't separate letters with the same code, but vowels do.
+        if c == 'h' || c == 'w' {
+            continue;
+        }
+        let digit = soundex_digit(c);
+        if let Some(d) = digit {
+            if digit != last_digit {
+                code.push(d);
+                if code.len() == 4 {
+                    break;
+                }
+            }
+        }
+        last_digit = digit;
+    }
+    while code.len() < 4 {
+        code.push('0');
+    }
+    Some(code)
+}
+
+fn soundex_digit(c: char) -> Option<char> {
+    match c {
+        'b' | 'f' | 'p' | 'v' => Some('1'),
+        'c' | 'g' | 'j' | 'k' | 'q' | 's' | 'x' | 'z' => Some('2'),
+        'd' | 't' => Some('3'),
+        'l' => Some('4'),
+        'm' | 'n' => Some('5'),
+        'r' => Some('6'),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn soundex_known_values() {
+        let code = |word| soundex(word).unwrap();
+        assert_eq!(code("robert"), "r163");
+        assert_eq!(code("rupert"), "r163");
+        assert_eq!(code("ashcraft"), "a261");
+        assert_eq!(code("tymczak"), "t522");
+        assert_eq!(code("pfister"), "p236");
+        assert_eq!(code("honeyman"), "h555");
+        assert_eq!(code("lee"), "l000");
+    }
+
+    #[test]
+    fn soundex_sound_alikes() {
+        assert_eq!(soundex("jon"), soundex("john"));
+        assert_eq!(soundex("smyth"), soundex("smith"));
+    }
+
+    #[test]
+    fn soundex_no_letters() {
+        assert_eq!(soundex("123"), None);
+        assert_eq!(soundex(""), None);
+    }
+}
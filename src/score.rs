@@ -0,0 +1,100 @@
+use std::collections::HashSet;
+use std::hash::Hash;
+
+/// How well a candidate's stored index matches a query.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MatchScore {
+    /// The number of query hashes found in the candidate.
+    pub match_count: usize,
+    /// The fraction of the query hashes found in the candidate, from 0.0 to 1.0. An empty query has a coverage of 0.0.
+    pub coverage: f64,
+    /// The size of the intersection divided by the size of the union, from 0.0 to 1.0.
+    /// Padding in the candidate's index lowers this, so it is best used to rank candidates rather than to filter them.
+    pub jaccard: f64,
+}
+
+impl MatchScore {
+    /// True if this score is at least as good as the threshold on every measure.
+    pub fn meets(&self, threshold: &ScoreThreshold) -> bool {
+        self.coverage >= threshold.min_coverage && self.jaccard >= threshold.min_jaccard
+    }
+}
+
+/// The lowest scores a candidate may have and still be considered a match.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoreThreshold {
+    pub min_coverage: f64,
+    pub min_jaccard: f64,
+}
+
+impl Default for ScoreThreshold {
+    /// Require every query hash to be present, which is what a substring search needs.
+    fn default() -> ScoreThreshold {
+        ScoreThreshold {
+            min_coverage: 1.0,
+            min_jaccard: 0.0,
+        }
+    }
+}
+
+/// Score a candidate's stored index against the hashes of a query.
+/// This works for hashes of any width, so long as the query and the candidate were made with the same one.
+pub fn score_candidate<T: Eq + Hash>(query: &HashSet<T>, candidate: &HashSet<T>) -> MatchScore {
+    let match_count = query.intersection(candidate).count();
+    let union_count = query.len() + candidate.len() - match_count;
+    MatchScore {
+        match_count,
+        coverage: ratio(match_count, query.len()),
+        jaccard: ratio(match_count, union_count),
+    }
+}
+
+///Divide, treating anything divided by 0 as 0.
+fn ratio(numerator: usize, denominator: usize) -> f64 {
+    if denominator == 0 {
+        0.0
+    } else {
+        numerator as f64 / denominator as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn score_candidate_partial_match() {
+        let query: HashSet<u32> = [1, 2, 3, 4].iter().copied().collect();
+        let candidate: HashSet<u32> = [3, 4, 5, 6].iter().copied().collect();
+        let score = score_candidate(&query, &candidate);
+        assert_eq!(score.match_count, 2);
+        assert_eq!(score.coverage, 0.5);
+        assert_eq!(score.jaccard, 2.0 / 6.0);
+        assert!(!score.meets(&ScoreThreshold::default()));
+        assert!(score.meets(&ScoreThreshold {
+            min_coverage: 0.5,
+            min_jaccard: 0.3
+        }));
+    }
+
+    #[test]
+    fn score_candidate_full_match() {
+        let query: HashSet<u64> = [1, 2].iter().copied().collect();
+        let candidate: HashSet<u64> = [1, 2, 3, 4].iter().copied().collect();
+        let score = score_candidate(&query, &candidate);
+        assert_eq!(score.match_count, 2);
+        assert_eq!(score.coverage, 1.0);
+        assert_eq!(score.jaccard, 0.5);
+        assert!(score.meets(&ScoreThreshold::default()));
+    }
+
+    #[test]
+    fn score_candidate_empty_query() {
+        let candidate: HashSet<u32> = [1, 2].iter().copied().collect();
+        let score = score_candidate(&HashSet::new(), &candidate);
+        assert_eq!(score.match_count, 0);
+        assert_eq!(score.coverage, 0.0);
+        assert_eq!(score.jaccard, 0.0);
+        assert!(!score.meets(&ScoreThreshold::default()));
+    }
+}
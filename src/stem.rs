@@ -0,0 +1,245 @@
+///Suffixes removed or replaced in step 2 when what's left has a measure of at least 1.
+///Where one suffix ends another, the longer one comes first.
+const STEP_2_RULES: &[(&str, &str)] = &[
+    ("ational", "ate"),
+    ("tional", "tion"),
+    ("enci", "ence"),
+    ("anci", "ance"),
+    ("izer", "ize"),
+    ("abli", "able"),
+    ("alli", "al"),
+    ("entli", "ent"),
+    ("eli", "e"),
+    ("ousli", "ous"),
+    ("ization", "ize"),
+    ("ation", "ate"),
+    ("ator", "ate"),
+    ("alism", "al"),
+    ("iveness", "ive"),
+    ("fulness", "ful"),
+    ("ousness", "ous"),
+    ("aliti", "al"),
+    ("iviti", "ive"),
+    ("biliti", "ble"),
+];
+
+///Suffixes removed or replaced in step 3 when what's left has a measure of at least 1.
+const STEP_3_RULES: &[(&str, &str)] = &[
+    ("icate", "ic"),
+    ("ative", ""),
+    ("alize", "al"),
+    ("iciti", "ic"),
+    ("ical", "ic"),
+    ("ful", ""),
+    ("ness", ""),
+];
+
+///Suffixes removed in step 4 when what's left has a measure of at least 2. "ion" is handled on its own.
+const STEP_4_RULES: &[(&str, &str)] = &[
+    ("al", ""),
+    ("ance", ""),
+    ("ence", ""),
+    ("er", ""),
+    ("ic", ""),
+    ("able", ""),
+    ("ible", ""),
+    ("ant", ""),
+    ("ement", ""),
+    ("ment", ""),
+    ("ent", ""),
+    ("ou", ""),
+    ("ism", ""),
+    ("ate", ""),
+    ("iti", ""),
+    ("ous", ""),
+    ("ive", ""),
+    ("ize", ""),
+];
+
+///Reduce an English word to its stem using the Porter stemming algorithm, e.g. "running" and "runs" both become "run".
+///This expects a word that has already been through `transliterate_string`. Words that aren't made up only of
+///lowercase ASCII letters are returned as they are.
+pub(crate) fn stem(word: &str) -> String {
+    if word.len() <= 2 || !word.bytes().all(|b| b.is_ascii_lowercase()) {
+        return word.to_string();
+    }
+    let mut w = word.as_bytes().to_vec();
+    replace_suffix(
+        &mut w,
+        &[("sses", "ss"), ("ies", "i"), ("ss", "ss"), ("s", "")],
+        |_| true,
+    );
+    step_1b(&mut w);
+    if w.ends_with(b"y") && has_vowel(&w[..w.len() - 1]) {
+        let last = w.len() - 1;
+        w[last] = b'i';
+    }
+    replace_suffix(&mut w, STEP_2_RULES, |stem| measure(stem) > 0);
+    replace_suffix(&mut w, STEP_3_RULES, |stem| measure(stem) > 0);
+    step_4(&mut w);
+    step_5(&mut w);
+    String::from_utf8(w).expect("Only ASCII letters are ever added to the word.")
+}
+
+fn step_1b(w: &mut Vec<u8>) {
+    if w.ends_with(b"eed") {
+        if measure(&w[..w.len() - 3]) > 0 {
+            w.pop();
+        }
+        return;
+    }
+    let suffix_len = if w.ends_with(b"ed") {
+        2
+    } else if w.ends_with(b"ing") {
+        3
+    } else {
+        return;
+    };
+    let stem_len = w.len() - suffix_len;
+    if !has_vowel(&w[..stem_len]) {
+        return;
+    }
+    w.truncate(stem_len);
+    if w.ends_with(b"at") || w.ends_with(b"bl") || w.ends_with(b"iz") {
+        w.push(b'e');
+    } else if ends_double_consonant(w) && !matches!(w[w.len() - 1], b'l' | b's' | b'z') {
+        w.pop();
+    } else if measure(w) == 1 && ends_cvc(w) {
+        w.push(b'e');
+    }
+}
+
+fn step_4(w: &mut Vec<u8>) {
+    if w.ends_with(b"ion") {
+        let stem_len = w.len() - 3;
+        let stem = &w[..stem_len];
+        if measure(stem) > 1 && (stem.ends_with(b"s") || stem.ends_with(b"t")) {
+            w.truncate(stem_len);
+        }
+    } else {
+        replace_suffix(w, STEP_4_RULES, |stem| measure(stem) > 1);
+    }
+}
+
+fn step_5(w: &mut Vec<u8>) {
+    if w.ends_with(b"e") {
+        let stem = &w[..w.len() - 1];
+        let m = measure(stem);
+        if m > 1 || (m == 1 && !ends_cvc(stem)) {
+            w.pop();
+        }
+    }
+    if measure(w) > 1 && ends_double_consonant(w) && w.ends_with(b"l") {
+        w.pop();
+    }
+}
+
+///Replace the first suffix in rules that w ends with, if what's left in front of it meets condition.
+///Later rules are never tried once a suffix matches, even if the condition fails.
+fn replace_suffix(w: &mut Vec<u8>, rules: &[(&str, &str)], condition: fn(&[u8]) -> bool) {
+    for (suffix, replacement) in rules {
+        if w.ends_with(suffix.as_bytes()) {
+            let stem_len = w.len() - suffix.len();
+            if condition(&w[..stem_len]) {
+                w.truncate(stem_len);
+                w.extend_from_slice(replacement.as_bytes());
+            }
+            return;
+        }
+    }
+}
+
+fn is_consonant(w: &[u8], i: usize) -> bool {
+    match w[i] {
+        b'a' | b'e' | b'i' | b'o' | b'u' => false,
+        b'y' => i == 0 || !is_consonant(w, i - 1),
+        _ => true,
+    }
+}
+
+///The number of times a run of vowels is followed by a run of consonants in w.
+fn measure(w: &[u8]) -> usize {
+    let mut m = 0;
+    let mut i = 0;
+    while i < w.len() && is_consonant(w, i) {
+        i += 1;
+    }
+    while i < w.len() {
+        while i < w.len() && !is_consonant(w, i) {
+            i += 1;
+        }
+        if i == w.len() {
+            break;
+        }
+        while i < w.len() && is_consonant(w, i) {
+            i += 1;
+        }
+        m += 1;
+    }
+    m
+}
+
+fn has_vowel(w: &[u8]) -> bool {
+    (0..w.len()).any(|i| !is_consonant(w, i))
+}
+
+fn ends_double_consonant(w: &[u8]) -> bool {
+    let n = w.len();
+    n >= 2 && w[n - 1] == w[n - 2] && is_consonant(w, n - 1)
+}
+
+///True if w ends consonant, vowel, consonant and the last consonant isn't 'w', 'x' or 'y', as in "hop" or "fil".
+fn ends_cvc(w: &[u8]) -> bool {
+    let n = w.len();
+    n >= 3
+        && is_consonant(w, n - 3)
+        && !is_consonant(w, n - 2)
+        && is_consonant(w, n - 1)
+        && !matches!(w[n - 1], b'w' | b'x' | b'y')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stem_known_values() {
+        let pairs = [
+            ("caresses", "caress"),
+            ("ponies", "poni"),
+            ("cats", "cat"),
+            ("feed", "feed"),
+            ("agreed", "agre"),
+            ("plastered", "plaster"),
+            ("motoring", "motor"),
+            ("sing", "sing"),
+            ("conflated", "conflat"),
+            ("hopping", "hop"),
+            ("falling", "fall"),
+            ("filing", "file"),
+            ("happy", "happi"),
+            ("sky", "sky"),
+            ("relational", "relat"),
+            ("generalization", "gener"),
+            ("electrical", "electr"),
+            ("adoption", "adopt"),
+            ("controlling", "control"),
+        ];
+        for (word, expected) in pairs.iter() {
+            assert_eq!(stem(word), *expected, "stem of {}", word);
+        }
+    }
+
+    #[test]
+    fn stem_matches_inflections() {
+        assert_eq!(stem("running"), "run");
+        assert_eq!(stem("runs"), "run");
+    }
+
+    #[test]
+    fn stem_leaves_other_words_alone() {
+        assert_eq!(stem("is"), "is");
+        assert_eq!(stem("a1b2"), "a1b2");
+        assert_eq!(stem("志"), "志");
+    }
+}
@@ -0,0 +1,815 @@
+use crate::normalize::TransliterationProfile;
+use crate::phonetic::soundex;
+use crate::stem::stem;
+use crate::IndexConfig;
+use std::collections::HashSet;
+use unicode_segmentation::UnicodeSegmentation;
+use unidecode::unidecode_char;
+
+/// Which n-grams of each word go into the index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NgramMode {
+    /// Every n-gram of every word.
+    All,
+    /// Only the prefixes of each word, from its first character up to the whole word (e.g. "j", "jo", "jos", "jose").
+    /// This only supports "starts with" searches, which is all typeahead needs, and exposes far less than `All`.
+    /// `IndexConfig::ngram_size` is only used to judge whether a query is selective in this mode.
+    Prefix,
+    /// Every n-gram of every word, after marking the start of each word with '^' and the end with '$'
+    /// (e.g. "^jo", "jos", "ose", "se$"). A search for a whole word or the start of a word then matches more
+    /// of its query hashes than a search that lands in the middle of a word, which `score_candidate` can use for ranking.
+    /// Queries are marked the same way, so use a `ScoreThreshold` with `min_coverage` below 1.0 to also find mid-word matches.
+    Anchored,
+}
+
+///The stop words Lucene uses for English.
+const ENGLISH_STOP_WORDS: [&str; 33] = [
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "if", "in", "into", "is", "it",
+    "no", "not", "of", "on", "or", "such", "that", "the", "their", "then", "there", "these",
+    "they", "this", "to", "was", "will", "with",
+];
+
+/// A built-in list of common English words to use for `IndexConfig::stop_words`.
+pub fn english_stop_words() -> HashSet<String> {
+    ENGLISH_STOP_WORDS.iter().map(|&w| w.to_string()).collect()
+}
+
+///Words shorter than this are only tokenized whole for fuzzy matching, since taking a character out of them leaves
+///too little to be worth looking up.
+const MIN_FUZZY_WORD_LEN: usize = 3;
+
+//Mark the start and end of each word for `NgramMode::Anchored`. Word segmentation never puts either of them in a word,
+//so they can't be confused with the input.
+const WORD_START: char = '^';
+const WORD_END: char = '$';
+
+///Never appears in UTF-8, so a tagged token can never be confused with an untagged n-gram.
+const DOMAIN_TAG_MARKER: u8 = 0xFF;
+
+///The kinds of token that get hashed. Each kind other than n-grams is hashed with its own tag in front of it,
+///so tokens of different kinds can never produce the same hash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum TokenDomain {
+    ///Left untagged so that existing indexes keep matching.
+    Ngram,
+    Exact,
+    Word,
+    Phonetic,
+    Fuzzy,
+}
+
+impl TokenDomain {
+    fn tag(self) -> Option<u8> {
+        match self {
+            TokenDomain::Ngram => None,
+            TokenDomain::Exact => Some(1),
+            TokenDomain::Word => Some(2),
+            TokenDomain::Phonetic => Some(3),
+            TokenDomain::Fuzzy => Some(4),
+        }
+    }
+}
+
+///A value to be hashed, along with the kind of token it is.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct Token {
+    domain: TokenDomain,
+    value: String,
+}
+
+impl Token {
+    pub(crate) fn new(domain: TokenDomain, value: String) -> Token {
+        Token { domain, value }
+    }
+
+    ///The bytes to hash for this token.
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        match self.domain.tag() {
+            None => self.value.as_bytes().to_vec(),
+            Some(tag) => {
+                let mut bytes = Vec::with_capacity(self.value.len() + 2);
+                bytes.push(DOMAIN_TAG_MARKER);
+                bytes.push(tag);
+                bytes.extend_from_slice(self.value.as_bytes());
+                bytes
+            }
+        }
+    }
+}
+
+///All the tokens that go into the index for s.
+pub(crate) fn make_tokens(s: &str, config: &IndexConfig) -> HashSet<Token> {
+    let mut tokens = make_profile_tokens(s, config);
+    if config.transliteration.also_indexes_standard() {
+        //Also index the standard transliteration, so queries written without the profile's spellings still match.
+        let standard = IndexConfig {
+            transliteration: TransliterationProfile::Standard,
+            ..config.clone()
+        };
+        tokens.extend(make_profile_tokens(s, &standard));
+    }
+    tokens
+}
+
+///The tokens for s using just the transliteration profile in config.
+fn make_profile_tokens(s: &str, config: &IndexConfig) -> HashSet<Token> {
+    let mut tokens = tag_all(TokenDomain::Ngram, make_index_ngrams(s, config));
+    if config.word_tokens {
+        tokens.extend(tag_all(TokenDomain::Word, index_words(s, config)));
+    }
+    if config.phonetic_tokens {
+        tokens.extend(make_phonetic_query_tokens(s, config));
+    }
+    if config.fuzzy_tokens {
+        tokens.extend(make_fuzzy_tokens_by_word(s, config).into_iter().flatten());
+    }
+    tokens
+}
+
+///The tokens to look up in the index to search for s by its n-grams.
+pub(crate) fn make_query_tokens(s: &str, config: &IndexConfig) -> HashSet<Token> {
+    tag_all(TokenDomain::Ngram, make_query_ngrams(s, config))
+}
+
+///The tokens to look up in the index to search for each of the words of s.
+pub(crate) fn make_word_query_tokens(s: &str, config: &IndexConfig) -> HashSet<Token> {
+    tag_all(TokenDomain::Word, index_words(s, config))
+}
+
+///The tokens to look up in the index to search for words that sound like each of the words of s.
+pub(crate) fn make_phonetic_query_tokens(s: &str, config: &IndexConfig) -> HashSet<Token> {
+    tag_all(
+        TokenDomain::Phonetic,
+        kept_words(s, config)
+            .iter()
+            .filter_map(|word| soundex(word)),
+    )
+}
+
+///For each distinct word of s, the tokens for fuzzy matching: the word itself and every way of taking one character out of it.
+///Two words that differ by one typo (a wrong, missing, extra or swapped character) always share at least one of these.
+pub(crate) fn make_fuzzy_tokens_by_word(s: &str, config: &IndexConfig) -> Vec<HashSet<Token>> {
+    let words: HashSet<String> = index_words(s, config).into_iter().collect();
+    words
+        .iter()
+        .map(|word| tag_all(TokenDomain::Fuzzy, deletion_variants(word)))
+        .collect()
+}
+
+fn deletion_variants(word: &str) -> HashSet<String> {
+    let chars: Vec<char> = word.chars().collect();
+    let mut variants: HashSet<String> = std::iter::once(word.to_string()).collect();
+    if chars.len() >= MIN_FUZZY_WORD_LEN {
+        variants.extend((0..chars.len()).map(|i| {
+            chars[..i]
+                .iter()
+                .chain(chars[i + 1..].iter())
+                .collect::<String>()
+        }));
+    }
+    variants
+}
+
+fn tag_all<I: IntoIterator<Item = String>>(domain: TokenDomain, values: I) -> HashSet<Token> {
+    values
+        .into_iter()
+        .map(|value| Token::new(domain, value))
+        .collect()
+}
+
+///The n-grams that go into the index for s.
+fn make_index_ngrams(s: &str, config: &IndexConfig) -> HashSet<String> {
+    let words = index_words(s, config);
+    match config.ngram_mode {
+        NgramMode::Prefix => words
+            .iter()
+            .flat_map(|word| word_to_prefixes(word))
+            .collect(),
+        NgramMode::All | NgramMode::Anchored => make_substring_ngrams(&words, config),
+    }
+}
+
+///The n-grams to look up in the index to search for s.
+///For prefixes each query word is looked up whole, since its shorter prefixes would only make the query less selective.
+fn make_query_ngrams(s: &str, config: &IndexConfig) -> HashSet<String> {
+    let words = index_words(s, config);
+    match config.ngram_mode {
+        NgramMode::Prefix => words.into_iter().collect(),
+        NgramMode::All | NgramMode::Anchored => make_substring_ngrams(&words, config),
+    }
+}
+
+///The n-grams for the modes that can match anywhere in a word. If the config asks for it, runs of CJK characters
+///get bigrams instead.
+fn make_substring_ngrams(words: &[String], config: &IndexConfig) -> HashSet<String> {
+    let (cjk_runs, words): (Vec<String>, Vec<String>) = words
+        .iter()
+        .cloned()
+        .partition(|word| config.cjk_bigrams && word.chars().all(is_cjk));
+    let mut ngrams = if config.ngram_mode == NgramMode::Anchored {
+        make_anchored_ngrams(&words, config.ngram_size)
+    } else {
+        words_to_ngrams(&words, config.ngram_size)
+    };
+    ngrams.extend(cjk_runs.iter().flat_map(|run| cjk_bigrams(run)));
+    ngrams
+}
+
+///The overlapping bigrams of a run of CJK characters. A run of one character is kept whole.
+fn cjk_bigrams(run: &str) -> HashSet<String> {
+    if run.chars().count() == 1 {
+        std::iter::once(run.to_string()).collect()
+    } else {
+        word_to_ngrams(run, 2)
+    }
+}
+
+///True for Han ideographs, Hiragana, Katakana and Hangul.
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{1100}'..='\u{11FF}'
+        | '\u{3040}'..='\u{30FF}'
+        | '\u{3130}'..='\u{318F}'
+        | '\u{31F0}'..='\u{31FF}'
+        | '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{AC00}'..='\u{D7AF}'
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{FF66}'..='\u{FF9F}'
+        | '\u{20000}'..='\u{3134F}')
+}
+
+///The whole of s normalized for exact matching. Only runs of whitespace are collapsed, so differences in spacing don't
+///matter but every character `config.char_filter` keeps is part of the token.
+pub(crate) fn make_exact_token(s: &str, config: &IndexConfig) -> String {
+    transliterate_with_config(s, config)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+///The words of s after it has been normalized the same way as for n-grams.
+///If `config.cjk_bigrams` is set, each run of CJK characters is a single word, even if it would otherwise be split.
+pub(crate) fn normalized_words(s: &str, config: &IndexConfig) -> Vec<String> {
+    let transliterated = transliterate_with_config(s, config);
+    if !config.cjk_bigrams {
+        return unicode_words(&transliterated);
+    }
+    let mut words = Vec::new();
+    let mut rest = String::new();
+    let mut run = String::new();
+    for c in transliterated.chars() {
+        if is_cjk(c) {
+            words.extend(unicode_words(&rest));
+            rest.clear();
+            run.push(c);
+        } else {
+            if !run.is_empty() {
+                words.push(run.clone());
+                run.clear();
+            }
+            rest.push(c);
+        }
+    }
+    words.extend(unicode_words(&rest));
+    if !run.is_empty() {
+        words.push(run);
+    }
+    words
+}
+
+fn unicode_words(s: &str) -> Vec<String> {
+    s.unicode_words().map(|word| word.to_string()).collect()
+}
+
+///The words of s that n-grams and word tokens are made from, stemmed if the config asks for it.
+pub(crate) fn index_words(s: &str, config: &IndexConfig) -> Vec<String> {
+    let words = kept_words(s, config);
+    if config.stem_words {
+        words.iter().map(|word| stem(word)).collect()
+    } else {
+        words
+    }
+}
+
+///Every form of the words of s that an index built with config holds: the profile's own, and also the standard
+///transliteration's for the profiles that index it.
+pub(crate) fn index_word_forms(s: &str, config: &IndexConfig) -> Vec<Vec<String>> {
+    let mut forms = vec![index_words(s, config)];
+    if config.transliteration.also_indexes_standard() {
+        let standard = IndexConfig {
+            transliteration: TransliterationProfile::Standard,
+            ..config.clone()
+        };
+        forms.push(index_words(s, &standard));
+    }
+    forms
+}
+
+///The normalized words of s, minus the stop words.
+fn kept_words(s: &str, config: &IndexConfig) -> Vec<String> {
+    normalized_words(s, config)
+        .into_iter()
+        .filter(|word| !config.stop_words.contains(word))
+        .collect()
+}
+
+fn make_anchored_ngrams(words: &[String], n: usize) -> HashSet<String> {
+    words
+        .iter()
+        .map(|word| pad_word(&format!("{}{}{}", WORD_START, word, WORD_END), n))
+        .flat_map(|word| word_to_ngrams(&word, n))
+        .collect()
+}
+
+fn word_to_prefixes(word: &str) -> HashSet<String> {
+    word.char_indices()
+        .map(|(i, c)| word[..i + c.len_utf8()].to_string())
+        .collect()
+}
+
+///The same as `transliterate_string`, but normalizing and filtering the way config asks.
+pub(crate) fn transliterate_with_config(s: &str, config: &IndexConfig) -> String {
+    config
+        .normalization
+        .normalize(s)
+        .chars()
+        .filter(|&c| config.char_filter.keeps(c))
+        .map(|c| {
+            if config.cjk_bigrams && is_cjk(c) {
+                c.to_string()
+            } else if config.transliteration.keeps_script() {
+                //Lowercasing one char at a time never gives a final sigma, so fold the ones in the input to match.
+                c.to_lowercase()
+                    .map(|lower| if lower == 'ς' { 'σ' } else { lower })
+                    .collect()
+            } else if let Some(trans) = config.transliteration.transliterate(c) {
+                trans.to_string()
+            } else {
+                char_to_trans(c)
+            }
+        })
+        .collect()
+}
+
+///If a word is shorter than n, '-' padding will be added to the end.
+///All Strings inside of the resulting set will always be of size n.
+fn words_to_ngrams(words: &[String], n: usize) -> HashSet<String> {
+    words
+        .iter()
+        .map(|short_word| pad_word(short_word, n))
+        .flat_map(|word| word_to_ngrams(&word, n))
+        .collect()
+}
+
+///If word is shorter than n, '-' padding will be added to the end.
+fn pad_word(short_word: &str, n: usize) -> String {
+    let short_word_len = short_word.chars().count();
+    if short_word_len < n {
+        //Pad the short_word with
+        format!("{:-<n$}", short_word, n = n)
+    } else {
+        short_word.to_string()
+    }
+}
+
+fn word_to_ngrams(s: &str, n: usize) -> HashSet<String> {
+    let chars: Vec<char> = s.chars().collect();
+    chars
+        .windows(n)
+        .map(|window| window.iter().collect())
+        .collect()
+}
+
+///Convert the char if we can, if we can't just create a string out of the character.
+fn char_to_trans(c: char) -> String {
+    let trans_string = unidecode_char(c);
+    if trans_string.is_empty() {
+        format!("{}", c)
+    } else {
+        trans_string.to_lowercase()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CharFilter, NormalizationProfile};
+
+    fn make_set(array: &[&str]) -> HashSet<String> {
+        array.iter().map(|&s| From::from(s)).collect()
+    }
+
+    fn make_ngrams(s: &str, n: usize) -> HashSet<String> {
+        words_to_ngrams(&normalized_words(s, &IndexConfig::default()), n)
+    }
+
+    fn prefix_config() -> IndexConfig {
+        IndexConfig {
+            ngram_mode: NgramMode::Prefix,
+            ..IndexConfig::default()
+        }
+    }
+
+    #[test]
+    fn token_encode() {
+        let token = |domain| Token::new(domain, "abc".to_string()).encode();
+        assert_eq!(token(TokenDomain::Ngram), b"abc".to_vec());
+        assert_eq!(token(TokenDomain::Exact), vec![0xFF, 1, b'a', b'b', b'c']);
+        assert_eq!(token(TokenDomain::Word), vec![0xFF, 2, b'a', b'b', b'c']);
+        assert_eq!(
+            token(TokenDomain::Phonetic),
+            vec![0xFF, 3, b'a', b'b', b'c']
+        );
+    }
+
+    #[test]
+    fn make_tokens_adds_phonetic_codes() {
+        let config = IndexConfig {
+            phonetic_tokens: true,
+            ..IndexConfig::default()
+        };
+        let mut expected = tag_all(TokenDomain::Ngram, make_ngrams("Jon 42", 3));
+        expected.insert(Token::new(TokenDomain::Phonetic, "j500".to_string()));
+        assert_eq!(make_tokens("Jon 42", &config), expected);
+    }
+
+    #[test]
+    fn make_tokens_adds_words() {
+        let config = IndexConfig {
+            word_tokens: true,
+            ..IndexConfig::default()
+        };
+        let mut expected = tag_all(TokenDomain::Ngram, make_ngrams("José Li", 3));
+        expected.extend(tag_all(
+            TokenDomain::Word,
+            vec!["jose".to_string(), "li".to_string()],
+        ));
+        assert_eq!(make_tokens("José Li", &config), expected);
+        assert_eq!(
+            make_tokens("José Li", &IndexConfig::default()),
+            tag_all(TokenDomain::Ngram, make_ngrams("José Li", 3))
+        );
+    }
+
+    #[test]
+    fn make_exact_token_known() {
+        let config = IndexConfig::default();
+        assert_eq!(make_exact_token(" José  Núñez!", &config), "jose nunez");
+        assert_eq!(make_exact_token("a.b@c.com", &config), "abccom");
+    }
+
+    #[test]
+    fn make_index_ngrams_all_matches_make_ngrams() {
+        let config = IndexConfig::default();
+        assert_eq!(
+            make_index_ngrams("José Núñez", &config),
+            make_ngrams("José Núñez", 3)
+        );
+        assert_eq!(
+            make_query_ngrams("José Núñez", &config),
+            make_ngrams("José Núñez", 3)
+        );
+    }
+
+    #[test]
+    fn make_index_ngrams_stems_words() {
+        let config = IndexConfig {
+            stem_words: true,
+            word_tokens: true,
+            ..IndexConfig::default()
+        };
+        assert_eq!(make_index_ngrams("Running", &config), make_set(&["run"]));
+        assert_eq!(
+            make_tokens("runs", &config),
+            make_tokens("running", &config)
+        );
+        assert_eq!(
+            make_query_tokens("RUNS", &config),
+            make_query_tokens("running", &config)
+        );
+    }
+
+    #[test]
+    fn make_tokens_drops_stop_words() {
+        let config = IndexConfig {
+            stop_words: english_stop_words(),
+            word_tokens: true,
+            phonetic_tokens: true,
+            ..IndexConfig::default()
+        };
+        assert_eq!(
+            make_tokens("The Art of War", &config),
+            make_tokens("art war", &config)
+        );
+        assert_eq!(make_query_tokens("the AND", &config), HashSet::new());
+        assert_eq!(
+            make_index_ngrams("The Art", &IndexConfig::default()),
+            make_set(&["the", "art"])
+        );
+    }
+
+    #[test]
+    fn normalized_words_uses_char_filter() {
+        let config = IndexConfig {
+            char_filter: CharFilter::Deny(HashSet::new()),
+            ..IndexConfig::default()
+        };
+        assert_eq!(
+            normalized_words("Jean-Luc a.b@c.com", &config),
+            vec!["jean", "luc", "a.b", "c.com"]
+        );
+        assert_eq!(
+            normalized_words("Jean-Luc a.b@c.com", &IndexConfig::default()),
+            vec!["jeanluc", "abccom"]
+        );
+    }
+
+    #[test]
+    fn normalized_words_v2_matches_lookalikes() {
+        let config = IndexConfig {
+            normalization: NormalizationProfile::V2,
+            ..IndexConfig::default()
+        };
+        assert_eq!(
+            normalized_words("E\u{301}cole №１２", &config),
+            normalized_words("École No12", &config)
+        );
+        assert_eq!(normalized_words("ǅemal", &config), vec!["dzemal"]);
+    }
+
+    #[test]
+    fn make_index_ngrams_cjk_bigrams() {
+        let config = IndexConfig {
+            cjk_bigrams: true,
+            ..IndexConfig::default()
+        };
+        assert_eq!(
+            make_index_ngrams("東京タワー 豪 Bo", &config),
+            make_set(&["東京", "京タ", "タワ", "ワー", "豪", "bo-"])
+        );
+        assert_eq!(
+            normalized_words("abc北京def 한국어", &config),
+            vec!["abc", "北京", "def", "한국어"]
+        );
+        assert_eq!(
+            make_index_ngrams("北亰", &IndexConfig::default()),
+            make_set(&["bei", "jin", "ing"])
+        );
+    }
+
+    #[test]
+    fn make_tokens_german_matches_both_spellings() {
+        let config = IndexConfig {
+            transliteration: TransliterationProfile::German,
+            ..IndexConfig::default()
+        };
+        let index = make_tokens("Müller", &config);
+        assert!(make_query_tokens("MÜLLER", &config).is_subset(&index));
+        assert!(make_query_tokens("mueller", &config).is_subset(&index));
+        assert!(make_query_tokens("muller", &config).is_subset(&index));
+        assert!(make_query_tokens("müller", &config).is_subset(&make_tokens("Mueller", &config)));
+        assert!(!make_query_tokens("müller", &config).is_subset(&make_tokens("Muller", &config)));
+    }
+
+    #[test]
+    fn normalized_words_v1_dotted_and_dotless_i() {
+        assert_eq!(
+            normalized_words("İSTANBUL ıIiİ", &IndexConfig::default()),
+            vec!["istanbul", "iiii"]
+        );
+    }
+
+    #[test]
+    fn normalized_words_v2_dotted_and_dotless_i() {
+        let config = IndexConfig {
+            normalization: NormalizationProfile::V2,
+            ..IndexConfig::default()
+        };
+        assert_eq!(
+            normalized_words("İSTANBUL ıIiİ", &config),
+            vec!["istanbul", "iiii"]
+        );
+    }
+
+    #[test]
+    fn make_tokens_v2_dotted_capital_i() {
+        let config = IndexConfig {
+            normalization: NormalizationProfile::V2,
+            ..IndexConfig::default()
+        };
+        assert_eq!(
+            make_tokens("İstanbul", &config),
+            make_tokens("Istanbul", &config)
+        );
+    }
+
+    #[test]
+    fn normalized_words_scandinavian() {
+        let config = IndexConfig {
+            transliteration: TransliterationProfile::Scandinavian,
+            ..IndexConfig::default()
+        };
+        assert_eq!(
+            normalized_words("Århus Søren", &config),
+            vec!["aarhus", "soeren"]
+        );
+    }
+
+    #[test]
+    fn normalized_words_native_keeps_script() {
+        let config = IndexConfig {
+            transliteration: TransliterationProfile::Native,
+            ..IndexConfig::default()
+        };
+        assert_eq!(
+            normalized_words("Москва ΟΔΟΣ شريط José!", &config),
+            vec!["москва", "οδοσ", "شريط", "josé"]
+        );
+        assert_eq!(
+            normalized_words("οδος", &config),
+            normalized_words("ΟΔΟΣ", &config)
+        );
+        assert_eq!(
+            make_index_ngrams("شريط", &config),
+            make_set(&["شري", "ريط"])
+        );
+        assert!(!make_query_tokens("jose", &config).is_subset(&make_tokens("José", &config)));
+    }
+
+    #[test]
+    fn make_tokens_dual_matches_either_form() {
+        let config = IndexConfig {
+            transliteration: TransliterationProfile::Dual,
+            ..IndexConfig::default()
+        };
+        let index = make_tokens("Москва José", &config);
+        for query in ["Москва", "МОСКВА", "moskva", "José", "jose"].iter() {
+            assert!(
+                make_query_tokens(query, &config).is_subset(&index),
+                "{}",
+                query
+            );
+        }
+        assert!(!make_query_tokens("José", &config).is_subset(&make_tokens("Jose", &config)));
+    }
+
+    #[test]
+    fn deletion_variants_known() {
+        assert_eq!(
+            deletion_variants("john"),
+            make_set(&["john", "ohn", "jhn", "jon", "joh"])
+        );
+        assert_eq!(deletion_variants("li"), make_set(&["li"]));
+    }
+
+    #[test]
+    fn make_fuzzy_tokens_share_a_token_with_one_typo() {
+        let config = IndexConfig {
+            fuzzy_tokens: true,
+            ..IndexConfig::default()
+        };
+        let index = make_tokens("John", &config);
+        for query in ["jhon", "jon", "johnn", "joan"].iter() {
+            let shared = make_fuzzy_tokens_by_word(query, &config)[0]
+                .intersection(&index)
+                .count();
+            assert!(shared >= 1, "{}", query);
+        }
+        let shared = make_fuzzy_tokens_by_word("jane", &config)[0]
+            .intersection(&index)
+            .count();
+        assert_eq!(shared, 0);
+    }
+
+    #[test]
+    fn make_index_ngrams_prefix_known() {
+        assert_eq!(
+            make_index_ngrams("José Li", &prefix_config()),
+            make_set(&["j", "jo", "jos", "jose", "l", "li"])
+        );
+    }
+
+    #[test]
+    fn make_index_ngrams_prefix_multibyte() {
+        assert_eq!(
+            make_index_ngrams("\u{102AE}\u{102AF}", &prefix_config()),
+            make_set(&["\u{102AE}", "\u{102AE}\u{102AF}"])
+        );
+    }
+
+    #[test]
+    fn make_index_ngrams_anchored_known() {
+        let config = IndexConfig {
+            ngram_mode: NgramMode::Anchored,
+            ..IndexConfig::default()
+        };
+        assert_eq!(
+            make_index_ngrams("José Li", &config),
+            make_set(&["^jo", "jos", "ose", "se$", "^li", "li$"])
+        );
+        assert_eq!(make_query_ngrams("J", &config), make_set(&["^j$"]));
+    }
+
+    #[test]
+    fn make_index_ngrams_anchored_pads_short_words() {
+        let config = IndexConfig {
+            ngram_mode: NgramMode::Anchored,
+            ngram_size: 5,
+            ..IndexConfig::default()
+        };
+        assert_eq!(make_index_ngrams("li", &config), make_set(&["^li$-"]));
+    }
+
+    #[test]
+    fn make_query_ngrams_prefix_uses_whole_words() {
+        assert_eq!(
+            make_query_ngrams("Jos N", &prefix_config()),
+            make_set(&["jos", "n"])
+        );
+    }
+
+    #[test]
+    fn word_to_trigrams_known() {
+        let result = word_to_ngrams("five", 3);
+        assert_eq!(result, make_set(&["fiv", "ive"]));
+    }
+
+    #[test]
+    fn make_tri_grams_works_multi_word() {
+        assert_eq!(
+            make_ngrams("123 José  Núñez 812-111-7654", 3),
+            make_set(&[
+                "123", "jos", "ose", "nun", "une", "nez", "812", "121", "211", "111", "117", "176",
+                "765", "654",
+            ])
+        );
+    }
+
+    #[test]
+    fn make_tri_grams_works_non_ascii() {
+        assert_eq!(
+            make_ngrams("TİRYAKİ", 3),
+            make_set(&["tir", "iry", "rya", "yak", "aki"])
+        );
+    }
+
+    #[test]
+    fn make_tri_grams_eliminates_duplicates() {
+        assert_eq!(
+            make_ngrams("TİRYAKİ TİRYAKİ", 3),
+            make_set(&["tir", "iry", "rya", "yak", "aki"])
+        );
+    }
+
+    #[test]
+    fn make_tri_grams_works_short_non_ascii() {
+        assert_eq!(make_ngrams("Tİ", 3), make_set(&["ti-"]));
+    }
+
+    #[test]
+    fn make_tri_grams_works_multichar_translate() {
+        assert_eq!(
+            make_ngrams("志    豪 İ", 3),
+            make_set(&["zhi", "hao", "i--"])
+        );
+    }
+
+    #[test]
+    fn make_tri_grams_works_arabic() {
+        assert_eq!(
+            make_ngrams("شريط فو", 3),
+            make_set(&["shr", "hry", "ryt", "fw-"])
+        );
+    }
+
+    #[test]
+    fn make_tri_grams_works_short_multibyte() {
+        assert_eq!(
+            make_ngrams("\u{102AE}\u{102AF}", 3),
+            make_set(&["\u{102AE}\u{102AF}-"])
+        );
+    }
+
+    #[test]
+    fn make_ngrams_works_bigrams() {
+        assert_eq!(make_ngrams("AB1 c", 2), make_set(&["ab", "b1", "c-"]));
+    }
+
+    #[test]
+    fn make_ngrams_pads_to_n() {
+        assert_eq!(make_ngrams("José Li", 5), make_set(&["jose-", "li---"]));
+        assert_eq!(make_ngrams("Núñez", 4), make_set(&["nune", "unez"]));
+    }
+
+    #[test]
+    fn char_to_trans_latinizable() {
+        assert_eq!(char_to_trans('İ'), "i")
+    }
+
+    #[test]
+    fn char_to_trans_not_latinizable() {
+        let c = "\u{102AE}".chars().next().unwrap();
+        assert_eq!(char_to_trans(c), "\u{102AE}")
+    }
+}
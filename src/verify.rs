@@ -0,0 +1,221 @@
+use crate::tokenize::{index_word_forms, index_words, make_query_tokens, make_tokens};
+use crate::IndexConfig;
+
+/// What it means for a decrypted candidate to really match a query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MatchMode {
+    /// Every token the query would look up is a token of the candidate. This is exactly what the index checks,
+    /// minus the hash collisions and padding.
+    AllTokens,
+    /// The query's words appear in the candidate, in order and next to each other. The first and last query words
+    /// may match the middle of a candidate word.
+    Substring,
+    /// Every word of the query is the start of some word in the candidate.
+    WordPrefix,
+}
+
+/// Check a decrypted candidate against the query it was found with, so false positives from hash truncation
+/// and padding can be dropped. Both strings go through the same normalization, stop words and stemming as they do
+/// for indexing, so `config` should be the one that was used to build the index. A query with no words left after
+/// that never verifies, since it would otherwise match every candidate.
+pub fn verify_candidate(
+    query: &str,
+    candidate: &str,
+    mode: MatchMode,
+    config: &IndexConfig,
+) -> bool {
+    let query_words = index_words(query, config);
+    if query_words.is_empty() {
+        return false;
+    }
+    match mode {
+        MatchMode::AllTokens => {
+            make_query_tokens(query, config).is_subset(&make_tokens(candidate, config))
+        }
+        MatchMode::Substring => {
+            //Join the words with single spaces so differences in punctuation and spacing don't matter.
+            let query_words = query_words.join(" ");
+            index_word_forms(candidate, config)
+                .iter()
+                .any(|candidate_words| candidate_words.join(" ").contains(&query_words))
+        }
+        MatchMode::WordPrefix => {
+            index_word_forms(candidate, config)
+                .iter()
+                .any(|candidate_words| {
+                    query_words.iter().all(|query_word| {
+                        candidate_words
+                            .iter()
+                            .any(|candidate_word| candidate_word.starts_with(query_word.as_str()))
+                    })
+                })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{english_stop_words, NormalizationProfile, TransliterationProfile};
+
+    #[test]
+    fn verify_candidate_empty_query() {
+        for &mode in [
+            MatchMode::AllTokens,
+            MatchMode::Substring,
+            MatchMode::WordPrefix,
+        ]
+        .iter()
+        {
+            assert!(!verify_candidate("", "José", mode, &IndexConfig::default()));
+            assert!(!verify_candidate(
+                "@.!",
+                "José",
+                mode,
+                &IndexConfig::default()
+            ));
+        }
+    }
+
+    #[test]
+    fn verify_candidate_all_tokens() {
+        assert!(verify_candidate(
+            "jose",
+            "123 José Núñez",
+            MatchMode::AllTokens,
+            &IndexConfig::default()
+        ));
+        //"ose" and "jos" are both present, but not as "jose".
+        assert!(verify_candidate(
+            "jose",
+            "jos rose",
+            MatchMode::AllTokens,
+            &IndexConfig::default()
+        ));
+        assert!(!verify_candidate(
+            "josh",
+            "José",
+            MatchMode::AllTokens,
+            &IndexConfig::default()
+        ));
+    }
+
+    #[test]
+    fn verify_candidate_all_tokens_uses_ngram_size() {
+        let bigrams = IndexConfig {
+            ngram_size: 2,
+            ..IndexConfig::default()
+        };
+        assert!(verify_candidate(
+            "jo",
+            "José",
+            MatchMode::AllTokens,
+            &bigrams
+        ));
+        assert!(!verify_candidate(
+            "jo",
+            "José",
+            MatchMode::AllTokens,
+            &IndexConfig::default()
+        ));
+    }
+
+    #[test]
+    fn verify_candidate_substring() {
+        assert!(verify_candidate(
+            "JOSE nun",
+            "123 José  Núñez",
+            MatchMode::Substring,
+            &IndexConfig::default()
+        ));
+        assert!(!verify_candidate(
+            "jose",
+            "jos rose",
+            MatchMode::Substring,
+            &IndexConfig::default()
+        ));
+        assert!(!verify_candidate(
+            "nunez jose",
+            "José Núñez",
+            MatchMode::Substring,
+            &IndexConfig::default()
+        ));
+    }
+
+    #[test]
+    fn verify_candidate_word_prefix() {
+        assert!(verify_candidate(
+            "nu jo",
+            "José Núñez",
+            MatchMode::WordPrefix,
+            &IndexConfig::default()
+        ));
+        assert!(!verify_candidate(
+            "ose",
+            "José Núñez",
+            MatchMode::WordPrefix,
+            &IndexConfig::default()
+        ));
+    }
+
+    #[test]
+    fn verify_candidate_uses_standard_form() {
+        let config = IndexConfig {
+            transliteration: TransliterationProfile::German,
+            ..IndexConfig::default()
+        };
+        for &mode in [MatchMode::Substring, MatchMode::WordPrefix].iter() {
+            assert!(verify_candidate("muller", "Müller", mode, &config));
+            assert!(verify_candidate("mueller", "Müller", mode, &config));
+            assert!(!verify_candidate("müller", "Muller", mode, &config));
+        }
+    }
+
+    #[test]
+    fn verify_candidate_uses_stems_and_stop_words() {
+        let stemmed = IndexConfig {
+            stem_words: true,
+            ..IndexConfig::default()
+        };
+        let stopped = IndexConfig {
+            stop_words: english_stop_words(),
+            ..IndexConfig::default()
+        };
+        for &mode in [MatchMode::Substring, MatchMode::WordPrefix].iter() {
+            assert!(verify_candidate("running", "he runs", mode, &stemmed));
+            assert!(verify_candidate(
+                "art war",
+                "The Art of War",
+                mode,
+                &stopped
+            ));
+            assert!(!verify_candidate(
+                "the of",
+                "The Art of War",
+                mode,
+                &stopped
+            ));
+        }
+    }
+
+    #[test]
+    fn verify_candidate_keeps_combining_dots() {
+        let config = IndexConfig {
+            normalization: NormalizationProfile::V2,
+            transliteration: TransliterationProfile::Native,
+            ..IndexConfig::default()
+        };
+        assert!(!verify_candidate(
+            "q\u{307}x",
+            "qx",
+            MatchMode::Substring,
+            &config
+        ));
+        assert!(verify_candidate(
+            "istanbul",
+            "İstanbul",
+            MatchMode::Substring,
+            &config
+        ));
+    }
+}